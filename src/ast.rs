@@ -3,6 +3,7 @@ use std::borrow::Cow;
 
 pub trait AsAst {
   type Error;
+  #[allow(clippy::result_large_err)]
   fn as_ast(&self) -> Result<Ast<'_>, (Ast<'_>, Vec<Self::Error>)>;
 }
 
@@ -11,8 +12,27 @@ pub struct Ast<'src> {
   pub routes: Routes<'src>,
   pub types: Types<'src>,
   pub schemes: SecuritySchemes<'src>,
-  /// Default security scheme
-  pub security: Option<Security<'src>>,
+  /// Requirements of operations which don't declare their own
+  pub security: SecurityRequirements<'src>,
+  pub servers: Servers<'src>,
+}
+
+pub type Servers<'src> = Vec<Server<'src>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Server<'src> {
+  /// URL template, where `{name}` is substituted by a variable
+  pub url: Cow<'src, str>,
+  pub description: Option<Cow<'src, str>>,
+  pub variables: IndexMap<Cow<'src, str>, ServerVariable<'src>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerVariable<'src> {
+  pub default: Cow<'src, str>,
+  /// Allowed values, any string is accepted when empty
+  pub enumeration: Vec<Cow<'src, str>>,
+  pub description: Option<Cow<'src, str>>,
 }
 
 pub type Routes<'src> = Vec<Route<'src>>;
@@ -26,10 +46,15 @@ pub struct Route<'src> {
   pub parameters: Parameters<'src>,
   pub request_body: Option<RequestBody<'src>>,
   pub responses: Responses<'src>,
-  pub security: Option<Security<'src>>,
+  pub security: SecurityRequirements<'src>,
+  /// Operation or path level overrides of the global servers
+  pub servers: Servers<'src>,
 }
 
-pub type Parameters<'src> = IndexMap<Cow<'src, str>, Parameter<'src>>;
+/// Parameters keyed by name and location, a path and a query parameter may
+/// share the same name
+pub type Parameters<'src> =
+  IndexMap<(Cow<'src, str>, ParameterKind), Parameter<'src>>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parameter<'src> {
@@ -37,28 +62,103 @@ pub struct Parameter<'src> {
   pub description: Option<Cow<'src, str>>,
   pub kind: ParameterKind,
   pub ty: TypeRef<'src>,
+  pub style: ParameterStyle,
+  pub explode: bool,
+  pub allow_reserved: bool,
+  /// Set when the parameter is described using `content` instead of `schema`
+  pub media_type: Option<MediaType<'src>>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ParameterKind {
   Path,
   Query,
   Header,
+  Cookie,
+}
+
+impl ParameterKind {
+  pub fn as_str(&self) -> &'static str {
+    use ParameterKind::*;
+    match self {
+      Path => "path",
+      Query => "query",
+      Header => "header",
+      Cookie => "cookie",
+    }
+  }
+}
+
+/// How a parameter value is serialized, see
+/// <https://spec.openapis.org/oas/v3.0.3#style-values>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterStyle {
+  Matrix,
+  Label,
+  Form,
+  Simple,
+  SpaceDelimited,
+  PipeDelimited,
+  DeepObject,
+}
+
+impl ParameterStyle {
+  pub fn as_str(&self) -> &'static str {
+    use ParameterStyle::*;
+    match self {
+      Matrix => "matrix",
+      Label => "label",
+      Form => "form",
+      Simple => "simple",
+      SpaceDelimited => "spaceDelimited",
+      PipeDelimited => "pipeDelimited",
+      DeepObject => "deepObject",
+    }
+  }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct RequestBody<'src> {
-  pub mime_type: MimeType,
-  pub headers: Vec<Cow<'src, str>>,
+  /// Supported media types, in the order of preference
+  pub content: Vec<Content<'src>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Content<'src> {
+  pub media_type: MediaType<'src>,
   pub ty: TypeRef<'src>,
+  /// Serialization of individual properties, keyed by property name
+  pub encoding: IndexMap<Cow<'src, str>, Encoding<'src>>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
-pub struct Code(u64);
+/// Serialization of a single property of a form-urlencoded or multipart body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Encoding<'src> {
+  pub media_type: Option<MediaType<'src>>,
+  pub style: ParameterStyle,
+  pub explode: bool,
+  pub allow_reserved: bool,
+}
+
+/// Status code of a response. The ordering follows the precedence in which
+/// responses are matched, from the most specific to the least.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Code {
+  /// A single status code, e.g. `404`
+  Exact(u16),
+  /// A range of status codes, e.g. `4XX`, identified by its leading digit
+  Range(u8),
+  /// Any status code not covered by other responses
+  Default,
+}
 
 impl Code {
   pub fn is_ok(&self) -> bool {
-    (200..299).contains(&self.0)
+    match self {
+      Code::Exact(v) => (200..=299).contains(v),
+      Code::Range(v) => *v == 2,
+      Code::Default => false,
+    }
   }
 }
 
@@ -66,37 +166,115 @@ macro_rules! impl_code_from {
   ($n:ident) => {
     impl From<$n> for Code {
       fn from(v: $n) -> Self {
-        Self(v as u64)
+        Self::Exact(v.into())
       }
     }
   };
 }
-impl_code_from!(u64);
-impl_code_from!(u32);
 impl_code_from!(u16);
 impl_code_from!(u8);
 
+/// Wider integers fail to convert when the value does not fit into a `u16`
+macro_rules! impl_code_try_from {
+  ($n:ident) => {
+    impl TryFrom<$n> for Code {
+      type Error = std::num::TryFromIntError;
+      fn try_from(v: $n) -> Result<Self, Self::Error> {
+        u16::try_from(v).map(Self::Exact)
+      }
+    }
+  };
+}
+impl_code_try_from!(u64);
+impl_code_try_from!(u32);
+
 impl std::fmt::Display for Code {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    std::fmt::Debug::fmt(self, f)
+    match self {
+      Code::Exact(v) => write!(f, "{v}"),
+      Code::Range(v) => write!(f, "{v}XX"),
+      Code::Default => write!(f, "default"),
+    }
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
-pub struct Responses<'src> {
-  pub default: Option<Response<'src>>,
-  pub specific: Vec<(Code, Response<'src>)>,
-}
+/// Responses sorted by their [`Code`], from the most specific to the least
+pub type Responses<'src> = Vec<(Code, Response<'src>)>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Response<'src> {
-  pub body: Option<TypeRef<'src>>,
+  /// Alternative bodies in the order of preference. Empty for responses
+  /// without content, such as `204 No Content`
+  pub content: Vec<Content<'src>>,
+  pub headers: IndexMap<Cow<'src, str>, TypeRef<'src>>,
 }
 
+/// Alternatives, any one of which authorizes a request. Empty when requests
+/// are anonymous.
+pub type SecurityRequirements<'src> = Vec<SecurityRequirement<'src>>;
+
+/// Schemes which all have to be satisfied, along with the scopes they need.
+/// An empty requirement makes authentication optional.
+pub type SecurityRequirement<'src> =
+  IndexMap<Cow<'src, str>, Vec<Cow<'src, str>>>;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Security<'src> {
-  pub name: Cow<'src, str>,
-  pub key: Cow<'src, str>,
+pub enum Security<'src> {
+  ApiKey {
+    name: Cow<'src, str>,
+    /// Name of the header, query parameter or cookie
+    key: Cow<'src, str>,
+    location: ApiKeyLocation,
+  },
+  /// HTTP `Basic` authentication with a username and password
+  Basic { name: Cow<'src, str> },
+  /// HTTP `Bearer` authentication, `format` is a hint such as `JWT`
+  Bearer {
+    name: Cow<'src, str>,
+    format: Option<Cow<'src, str>>,
+  },
+  OAuth2 {
+    name: Cow<'src, str>,
+    flows: Vec<OAuth2Flow<'src>>,
+  },
+}
+
+impl<'src> Security<'src> {
+  pub fn name(&self) -> &Cow<'src, str> {
+    match self {
+      Security::ApiKey { name, .. }
+      | Security::Basic { name }
+      | Security::Bearer { name, .. }
+      | Security::OAuth2 { name, .. } => name,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OAuth2Flow<'src> {
+  pub kind: OAuth2FlowKind,
+  /// Absent for the `password` and `clientCredentials` flows
+  pub authorization_url: Option<Cow<'src, str>>,
+  /// Absent for the `implicit` flow
+  pub token_url: Option<Cow<'src, str>>,
+  pub refresh_url: Option<Cow<'src, str>>,
+  /// Scope names and their descriptions
+  pub scopes: IndexMap<Cow<'src, str>, Cow<'src, str>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OAuth2FlowKind {
+  Implicit,
+  Password,
+  ClientCredentials,
+  AuthorizationCode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiKeyLocation {
+  Header,
+  Query,
+  Cookie,
 }
 
 pub type SecuritySchemes<'src> = IndexMap<Cow<'src, str>, Security<'src>>;
@@ -130,6 +308,8 @@ pub enum Type<'src> {
   Any,
   Number,
   String,
+  /// A string with the `binary` format, such as a file upload
+  Binary,
   Boolean,
   Enum(Vec<Cow<'src, str>>),
   Array(Box<TypeRef<'src>>),
@@ -166,6 +346,13 @@ impl Method {
       Connect => "connect",
     }
   }
+
+  /// Whether sending a request twice has the same effect as sending it once,
+  /// which makes it safe to retry
+  pub fn is_idempotent(&self) -> bool {
+    use Method::*;
+    matches!(self, Get | Put | Delete | Head | Options | Trace)
+  }
 }
 
 impl<'src> TryFrom<&'src str> for Method {
@@ -206,46 +393,123 @@ impl std::fmt::Display for Method {
   }
 }
 
-#[allow(non_camel_case_types)]
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum MimeType {
-  Multipart_FormData,
-  Application_FormUrlEncoded,
-  Application_Json,
-  Text_Plain,
+/// A media type such as `application/merge-patch+json; charset=utf-8`
+#[derive(Clone, PartialEq, Eq)]
+pub struct MediaType<'src> {
+  pub ty: &'src str,
+  pub subtype: &'src str,
+  /// Structured syntax suffix, e.g. `json` in `application/vnd.api+json`
+  pub suffix: Option<&'src str>,
+  pub params: Vec<(&'src str, &'src str)>,
+}
+
+/// Determines how a body of a given media type is encoded and decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+  Json,
+  FormUrlEncoded,
+  Multipart,
+  Text,
+  /// `text/event-stream`
+  EventStream,
+  /// Newline-delimited JSON values
+  JsonLines,
+  Binary,
 }
 
-impl<'src> TryFrom<&'src str> for MimeType {
+impl MediaKind {
+  /// Streamed bodies are consumed incrementally instead of being read whole
+  pub fn is_stream(&self) -> bool {
+    matches!(self, MediaKind::EventStream | MediaKind::JsonLines)
+  }
+}
+
+impl<'src> MediaType<'src> {
+  pub fn kind(&self) -> MediaKind {
+    let is = |a: &str, b: &str| a.eq_ignore_ascii_case(b);
+    let suffix = self.suffix.unwrap_or("");
+    if is(self.ty, "text") && is(self.subtype, "event-stream") {
+      MediaKind::EventStream
+    } else if ["x-ndjson", "ndjson", "jsonl", "x-jsonlines", "jsonlines"]
+      .iter()
+      .any(|v| is(self.subtype, v))
+    {
+      MediaKind::JsonLines
+    } else if is(self.subtype, "json") || is(suffix, "json") {
+      MediaKind::Json
+    } else if is(self.ty, "application")
+      && is(self.subtype, "x-www-form-urlencoded")
+    {
+      MediaKind::FormUrlEncoded
+    } else if is(self.ty, "multipart") {
+      MediaKind::Multipart
+    } else if is(self.ty, "text")
+      || is(self.subtype, "xml")
+      || is(suffix, "xml")
+    {
+      MediaKind::Text
+    } else {
+      MediaKind::Binary
+    }
+  }
+}
+
+impl<'src> TryFrom<&'src str> for MediaType<'src> {
   type Error = ();
 
   fn try_from(value: &'src str) -> Result<Self, Self::Error> {
-    use MimeType::*;
-    Ok(match value {
-      "multipart/form-data" => Multipart_FormData,
-      "application/x-www-form-urlencoded" => Application_FormUrlEncoded,
-      "application/json" => Application_Json,
-      "text/plain" => Text_Plain,
-      _ => return Err(()),
+    let token = |v: &str| {
+      !v.is_empty()
+        && v
+          .chars()
+          .all(|c| c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?={}".contains(c))
+    };
+    let mut parts = value.split(';');
+    let (ty, subtype) = parts
+      .next()
+      .unwrap_or("")
+      .trim()
+      .split_once('/')
+      .ok_or(())?;
+    let (subtype, suffix) = match subtype.rsplit_once('+') {
+      Some((subtype, suffix)) => (subtype, Some(suffix)),
+      None => (subtype, None),
+    };
+    if !token(ty) || !token(subtype) || !suffix.map(token).unwrap_or(true) {
+      return Err(());
+    }
+    let mut params = vec![];
+    for param in parts {
+      let (name, value) = param.trim().split_once('=').ok_or(())?;
+      let (name, value) = (name.trim(), value.trim());
+      if !token(name) || value.is_empty() {
+        return Err(());
+      }
+      params.push((name, value));
+    }
+    Ok(MediaType {
+      ty,
+      subtype,
+      suffix,
+      params,
     })
   }
 }
 
-impl std::fmt::Debug for MimeType {
+impl<'src> std::fmt::Debug for MediaType<'src> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(
-      f,
-      "{}",
-      match self {
-        Self::Multipart_FormData => "multipart/form-data",
-        Self::Application_FormUrlEncoded => "application/x-www-form-urlencoded",
-        Self::Application_Json => "application/json",
-        Self::Text_Plain => "text/plain",
-      }
-    )
+    write!(f, "{}/{}", self.ty, self.subtype)?;
+    if let Some(suffix) = self.suffix {
+      write!(f, "+{suffix}")?;
+    }
+    for (name, value) in self.params.iter() {
+      write!(f, "; {name}={value}")?;
+    }
+    Ok(())
   }
 }
 
-impl std::fmt::Display for MimeType {
+impl<'src> std::fmt::Display for MediaType<'src> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     std::fmt::Debug::fmt(self, f)
   }
@@ -1,5 +1,6 @@
 #![allow(clippy::needless_borrow)]
 
+use indexmap::IndexMap;
 use std::borrow::Cow;
 
 use crate::{ast, util::trim_in_place};
@@ -71,11 +72,26 @@ impl<'src> std::fmt::Display for Token<'src> {
       Token::LowerThan => write!(f, "<"),
       Token::GreaterThan => write!(f, ">"),
       Token::Identifier(i) => write!(f, "{i}"),
-      Token::String(s) => write!(f, "'{s}'"),
+      Token::String(s) => {
+        write!(f, "'")?;
+        for c in s.chars() {
+          match c {
+            '\'' | '\\' => write!(f, "\\{c}")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            c => write!(f, "{c}")?,
+          }
+        }
+        write!(f, "'")
+      }
       Token::Doc(d) => {
         writeln!(f, "\n/**")?;
         for line in d.split('\n') {
-          writeln!(f, " * {line}")?;
+          if line.is_empty() {
+            writeln!(f, " *")?;
+          } else {
+            writeln!(f, " * {line}")?;
+          }
         }
         writeln!(f, "*/")?;
         Ok(())
@@ -85,8 +101,129 @@ impl<'src> std::fmt::Display for Token<'src> {
   }
 }
 
+/// Options which affect the shape of the generated code.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+  pub cookies: Cookies,
+  pub errors: Errors,
+  pub binary: Binary,
+  pub target: Target,
+}
+
+/// How cookie parameters and cookie API keys are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cookies {
+  /// Serialize them into a `Cookie` header. Browsers do not allow setting
+  /// this header, so this only works in server runtimes.
+  #[default]
+  Header,
+  /// Send requests with `credentials: 'include'`, and let the runtime
+  /// attach cookies from its cookie jar.
+  Include,
+}
+
+impl std::str::FromStr for Cookies {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "header" => Ok(Cookies::Header),
+      "include" => Ok(Cookies::Include),
+      _ => Err(format!("invalid cookie mode `{s}`")),
+    }
+  }
+}
+
+/// How non-success responses are surfaced to the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Errors {
+  /// Reject the returned promise with an `ApiError`.
+  #[default]
+  Throw,
+  /// Resolve to a `Result`, which holds either the data or an `ApiError`.
+  Result,
+}
+
+impl std::str::FromStr for Errors {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "throw" => Ok(Errors::Throw),
+      "result" => Ok(Errors::Result),
+      _ => Err(format!("invalid error mode `{s}`")),
+    }
+  }
+}
+
+/// How binary response bodies are returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Binary {
+  /// Read the whole body into a `Blob`.
+  #[default]
+  Blob,
+  /// Read the whole body into an `ArrayBuffer`.
+  ArrayBuffer,
+  /// Return the body as a `ReadableStream` without reading it.
+  Stream,
+}
+
+impl std::str::FromStr for Binary {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "blob" => Ok(Binary::Blob),
+      "arraybuffer" => Ok(Binary::ArrayBuffer),
+      "stream" => Ok(Binary::Stream),
+      _ => Err(format!("invalid binary mode `{s}`")),
+    }
+  }
+}
+
+/// Runtime the generated code runs in, which decides the global types it may
+/// use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+  /// Browsers, with the `dom` library.
+  #[default]
+  Browser,
+  /// Node.js 20 or later, the first with the global `crypto` and `File`,
+  /// with the types of `@types/node`.
+  Node,
+  /// Deno, with the `deno.ns` library.
+  Deno,
+  /// Web workers, with the `webworker` library.
+  Worker,
+}
+
+impl Target {
+  /// Node and Deno read files into `Uint8Array`s, which may be sent as is.
+  fn has_byte_arrays(self) -> bool {
+    matches!(self, Target::Node | Target::Deno)
+  }
+}
+
+impl std::str::FromStr for Target {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "browser" => Ok(Target::Browser),
+      "node" => Ok(Target::Node),
+      "deno" => Ok(Target::Deno),
+      "worker" => Ok(Target::Worker),
+      _ => Err(format!("invalid target `{s}`")),
+    }
+  }
+}
+
 pub struct Buffer<'src> {
   tokens: Vec<Token<'src>>,
+  options: Options,
+  /// Whether `binary` strings are sent as files, which is only possible in
+  /// multipart bodies
+  files: bool,
 }
 
 impl<'src> From<Buffer<'src>> for String {
@@ -108,11 +245,28 @@ impl<'src> Default for Buffer<'src> {
 
 impl<'src> Buffer<'src> {
   pub fn new() -> Self {
+    Self::with_options(Options::default())
+  }
+
+  pub fn with_options(options: Options) -> Self {
     Self {
       tokens: Vec::with_capacity(1024),
+      options,
+      files: false,
     }
   }
 
+  pub fn options(&self) -> &Options {
+    &self.options
+  }
+
+  /// Emits `binary` strings within `f` as files
+  pub fn files(&mut self, f: impl FnOnce(&mut Self)) {
+    let files = std::mem::replace(&mut self.files, true);
+    f(self);
+    self.files = files;
+  }
+
   pub fn extend(&mut self, iter: impl IntoIterator<Item = Token<'src>>) {
     self.tokens.extend(iter);
   }
@@ -224,20 +378,198 @@ impl<'src> Buffer<'src> {
 }
 
 pub fn emit<'src>(input: impl Emit<'src>) -> String {
-  let mut buffer = Buffer::new();
+  emit_with(input, Options::default())
+}
+
+pub fn emit_with<'src>(input: impl Emit<'src>, options: Options) -> String {
+  let mut buffer = Buffer::with_options(options);
   input.emit(&mut buffer);
   trim_in_place(buffer)
 }
 
+/// Helpers used by the generated code. Lines between `//#if <feature> ...`
+/// and `//#endif` are only emitted if the spec uses any of the features.
+const RUNTIME: &str = include_str!("emit/runtime.ts");
+
+/// The runtime without the sections of features which are not in `features`
+fn runtime(features: &[&str]) -> String {
+  let mut out = String::with_capacity(RUNTIME.len());
+  // whether each enclosing section is emitted
+  let mut sections: Vec<bool> = vec![];
+  for line in RUNTIME.lines() {
+    if let Some(names) = line.strip_prefix("//#if ") {
+      sections.push(names.split(' ').any(|name| features.contains(&name)));
+    } else if line == "//#endif" {
+      sections.pop();
+    } else if sections.iter().all(|emitted| *emitted) {
+      out.push_str(line);
+      out.push('\n');
+    }
+  }
+  out
+}
+
+/// What declares a module-level name of the generated code
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Declarer<'a, 'src> {
+  Runtime,
+  Client,
+  Server,
+  Scheme(&'a str),
+  Schema,
+  Route(&'a ast::Route<'src>),
+}
+
+impl std::fmt::Display for Declarer<'_, '_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Declarer::Runtime => write!(f, "the runtime"),
+      Declarer::Client => write!(f, "the client"),
+      Declarer::Server => write!(f, "a server"),
+      Declarer::Scheme(name) => write!(f, "the security scheme `{name}`"),
+      Declarer::Schema => write!(f, "a schema"),
+      Declarer::Route(route) => write!(f, "the operation `{}`", route.name),
+    }
+  }
+}
+
+/// Every name which the generated code declares at the module level or as a
+/// member of `ApiClient`, along with what declares it. Names of the runtime
+/// are included whichever features the spec uses.
+pub(crate) fn declared_names<'a, 'src>(
+  ast: &'a ast::Ast<'src>,
+) -> Vec<(Cow<'a, str>, Declarer<'a, 'src>)> {
+  const KEYWORDS: [&str; 9] = [
+    "async function* ",
+    "async function ",
+    "function* ",
+    "function ",
+    "class ",
+    "interface ",
+    "type ",
+    "const ",
+    "let ",
+  ];
+  let runtime = RUNTIME.lines().filter_map(|line| {
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let rest = KEYWORDS.iter().find_map(|k| line.strip_prefix(k))?;
+    let end = rest
+      .find(|c: char| !c.is_alphanumeric() && c != '_')
+      .unwrap_or(rest.len());
+    Some(&rest[..end])
+  });
+  let client = [
+    "ApiClient",
+    "_config",
+    "constructor",
+    "_client",
+    "_defaultClient",
+    "init",
+  ];
+  let mut names: Vec<(Cow<str>, Declarer)> = runtime
+    .map(|name| (name.into(), Declarer::Runtime))
+    .chain(client.map(|name| (name.into(), Declarer::Client)))
+    .collect();
+  for i in 0..ast.servers.len() {
+    names.push((format!("server{i}").into(), Declarer::Server));
+  }
+  for (name, scheme) in ast.schemes.iter() {
+    if let ast::Security::OAuth2 { .. } = scheme {
+      names.push((format!("{name}Client").into(), Declarer::Scheme(name)));
+    }
+  }
+  for name in ast.types.keys() {
+    names.push((name.as_ref().into(), Declarer::Schema));
+  }
+  for route in ast.routes.iter() {
+    let name = &route.name;
+    let by = Declarer::Route(route);
+    names.push((name.as_ref().into(), by));
+    for derived in [
+      format!("{name}Raw"),
+      format!("{name}Error"),
+      format!("_{name}"),
+    ] {
+      names.push((derived.into(), by));
+    }
+    for i in 0..route.servers.len() {
+      names.push((format!("{name}Server{i}").into(), by));
+    }
+  }
+  names
+}
+
+/// Optional features of the runtime which the spec uses, named after the
+/// response kinds they decode, the body kinds they encode and so on
+fn runtime_features(ast: &ast::Ast, options: &Options) -> Vec<&'static str> {
+  let mut features = vec![];
+  if !ast.schemes.is_empty() {
+    features.push("schemes");
+  }
+  for scheme in ast.schemes.values() {
+    match scheme {
+      ast::Security::Basic { .. } => features.push("basic"),
+      ast::Security::OAuth2 { .. } => features.push("oauth2"),
+      _ => {}
+    }
+  }
+  let mut servers = ast
+    .servers
+    .iter()
+    .chain(ast.routes.iter().flat_map(|r| &r.servers));
+  if servers.any(|server| !server.variables.is_empty()) {
+    features.push("serverUrl");
+  }
+  if options.errors == Errors::Result {
+    features.push("result");
+  }
+  for route in ast.routes.iter() {
+    if let Some(body) = &route.request_body {
+      features.push("body");
+      for content in body.content.iter() {
+        if content.media_type.kind() == ast::MediaKind::Multipart {
+          features.push("multipartBody");
+        }
+      }
+    }
+    for (_, res) in route.responses.iter() {
+      for content in res.content.iter() {
+        features.push(response_kind(content, options.binary));
+      }
+    }
+  }
+  features.sort_unstable();
+  features.dedup();
+  features
+}
+
 pub trait Emit<'src> {
   fn emit(self, buffer: &mut Buffer<'src>);
 }
 
 impl<'src> Emit<'src> for ast::Ast<'src> {
   fn emit(self, buffer: &mut Buffer<'src>) {
-    self.schemes.emit(buffer);
+    // references the declarations of the target's globals
+    buffer.raw(match buffer.options().target {
+      Target::Browser => "/// <reference lib=\"dom\" />\n",
+      Target::Node => "/// <reference types=\"node\" />\n",
+      Target::Deno => "/// <reference lib=\"deno.ns\" />\n",
+      Target::Worker => "/// <reference lib=\"webworker\" />\n",
+    });
+    buffer.raw(runtime(&runtime_features(&self, buffer.options())));
+    let servers = !self.servers.is_empty();
+    ServerFunctions("server".into(), &self.servers).emit(buffer);
+    for route in self.routes.iter().filter(|r| !r.servers.is_empty()) {
+      let prefix = format!("{}Server", route.name);
+      ServerFunctions(prefix.into(), &route.servers).emit(buffer);
+    }
     self.types.emit(buffer);
-    self.routes.emit(buffer);
+    Client {
+      schemes: self.schemes,
+      routes: self.routes,
+      servers,
+    }
+    .emit(buffer);
   }
 }
 
@@ -267,7 +599,7 @@ impl<'src> Emit<'src> for ast::TypeRef<'src> {
   }
 }
 
-impl<'a, 'src> Emit<'src> for &'a ast::TypeRef<'src> {
+impl<'src> Emit<'src> for &ast::TypeRef<'src> {
   fn emit(self, buffer: &mut Buffer<'src>) {
     match self {
       ast::TypeRef::Type(ty) => ty.emit(buffer),
@@ -282,7 +614,7 @@ impl<'src> Emit<'src> for ast::Type<'src> {
   }
 }
 
-impl<'a, 'src> Emit<'src> for &'a ast::Type<'src> {
+impl<'src> Emit<'src> for &ast::Type<'src> {
   fn emit(self, buffer: &mut Buffer<'src>) {
     match self {
       // any
@@ -301,6 +633,17 @@ impl<'a, 'src> Emit<'src> for &'a ast::Type<'src> {
       ast::Type::Boolean => {
         buffer.identifier("boolean");
       }
+      // (Blob | File) in multipart bodies, or Blob in Node, where older
+      // `@types/node` lack `File`. Elsewhere, such as in JSON, it is a string
+      ast::Type::Binary if !buffer.files => buffer.identifier("string"),
+      ast::Type::Binary => match buffer.options().target {
+        Target::Node => buffer.identifier("Blob"),
+        _ => buffer.parens(|buffer| {
+          buffer.identifier("Blob");
+          buffer.or();
+          buffer.identifier("File");
+        }),
+      },
       // ("a" | "b" | "c" | ...)
       ast::Type::Enum(v) => buffer.parens(|buffer| {
         let mut iter = v.iter();
@@ -352,483 +695,2483 @@ impl<'a, 'src> Emit<'src> for &'a ast::Type<'src> {
   }
 }
 
-impl<'src> Emit<'src> for ast::SecuritySchemes<'src> {
+/// Functions which resolve the URL of each server, `server#i` for the global
+/// ones and `#(route)Server#i` for the overrides of an operation
+struct ServerFunctions<'a, 'src>(Cow<'a, str>, &'a ast::Servers<'src>);
+impl<'a, 'src> Emit<'src> for ServerFunctions<'a, 'src> {
   fn emit(self, buffer: &mut Buffer<'src>) {
     /*
-    let _baseUrl: string = "";
-    let _authHeaders: Record<string, string> = {};
-    export function init(baseUrl: string, scheme0: string, scheme1: string, ...) {
-      _baseUrl = baseUrl;
-      _authHeaders = { 'key0': scheme0, 'key1': scheme1, ... }
+    /**
+     * #description
+     */
+    export function #prefix#i ( #(variables? \(variables : { #name ? : #type , ... } = { })) ) : string {
+      return _serverUrl ( #url , { #name : #default , ... variables } ) ;
     }
     */
-    buffer.raw("let _baseUrl : string = \"\" ;");
-    buffer.raw("let _authHeaders : Record < string , string > = { } ;");
-    buffer.raw("let _fetch : typeof window . fetch = window . fetch ;");
+    let ServerFunctions(prefix, servers) = self;
+    for (i, server) in servers.iter().enumerate() {
+      if let Some(desc) = server.description.clone() {
+        buffer.doc(desc);
+      }
+      buffer.raw("export function");
+      buffer.identifier(format!("{prefix}{i}"));
+      buffer.parens(|buffer| {
+        if !server.variables.is_empty() {
+          buffer.raw("variables :");
+          buffer.braces(|buffer| {
+            for (name, var) in server.variables.iter() {
+              buffer.string(name.clone());
+              buffer.question();
+              buffer.colon();
+              if var.enumeration.is_empty() {
+                buffer.identifier("string");
+              } else {
+                ast::Type::Enum(var.enumeration.clone()).emit(buffer);
+              }
+              buffer.comma();
+            }
+          });
+          buffer.equals();
+          buffer.raw("{ }");
+        }
+      });
+      buffer.colon();
+      buffer.raw("string");
+      buffer.braces(|buffer| {
+        buffer.raw("return");
+        ServerUrl(server, true).emit(buffer);
+        buffer.semicolon();
+      });
+    }
+  }
+}
 
-    buffer.raw("export function init");
+/// Substitutes the variables of a server URL, using their defaults unless
+/// `overridable` passes on a `variables` object
+struct ServerUrl<'a, 'src>(&'a ast::Server<'src>, bool);
+impl<'a, 'src> Emit<'src> for ServerUrl<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    /*
+    _serverUrl ( #url , { #name : #default , ... #(overridable? \(... variables)) } )
+    */
+    let ServerUrl(server, overridable) = self;
+    if server.variables.is_empty() {
+      buffer.string(server.url.clone());
+      return;
+    }
+    buffer.raw("_serverUrl");
     buffer.parens(|buffer| {
-      buffer.raw("baseUrl : string ,");
+      buffer.string(server.url.clone());
+      buffer.comma();
+      buffer.braces(|buffer| {
+        for (name, var) in server.variables.iter() {
+          buffer.string(name.clone());
+          buffer.colon();
+          buffer.string(var.default.clone());
+          buffer.comma();
+        }
+        if overridable {
+          buffer.raw("... variables");
+        }
+      });
+    });
+  }
+}
+
+/// The `ApiClient` class, which holds the configuration of the API per
+/// instance and has the functions of each route as methods, followed by free
+/// functions calling a default instance which `init` configures
+struct Client<'src> {
+  schemes: ast::SecuritySchemes<'src>,
+  routes: ast::Routes<'src>,
+  /// the base URL defaults to the first server, if there is one
+  servers: bool,
+}
+impl<'src> Emit<'src> for Client<'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    /*
+    #$each(oauth2 scheme) \(#scheme)
+    #$each(route) \(#route_error)
+
+    /**
+     * Client of the API, each instance has its own base URL, credentials and `fetch`
+     */
+    export class ApiClient {
+      _config : _Config ;
+      #client_doc
+      constructor ( #params ) {
+        this . _config = { baseUrl , security : { credentials : #credentials , #(schemes? \(onUnauthorized ,)) } , fetch , defaults , } ;
+      }
+      #$each(route) \(#route_methods)
+    }
+
+    let _client : ApiClient | undefined ;
+
+    /**
+     * The instance which the free functions call, created on first use unless `init` configured one
+     */
+    function _defaultClient ( ) : ApiClient {
+      return _client ??= new ApiClient ( ) ;
+    }
+
+    #client_doc
+    export function init ( #params ) {
+      _client = new ApiClient ( baseUrl , #$each(scheme) \(#scheme ,) fetch , #(schemes? \(onUnauthorized ,)) defaults , ) ;
+    }
+    #$each(route) \(#route_functions)
+    */
+    let Client {
+      schemes,
+      routes,
+      servers,
+    } = self;
+    let cookies = buffer.options().cookies;
 
-      for scheme in self.values() {
-        buffer.identifier(scheme.name.clone());
-        buffer.raw(": string ,");
+    for scheme in schemes.values() {
+      if let ast::Security::OAuth2 { name, flows } = scheme {
+        OAuth2Factory(name, flows).emit(buffer);
       }
+    }
+    for route in routes.iter() {
+      RouteError(route).emit(buffer);
+    }
+
+    let params = schemes
+      .values()
+      .filter_map(|scheme| match scheme {
+        ast::Security::ApiKey { .. } => None,
+        ast::Security::Basic { name } => {
+          Some(format!("@param {name} Username and password"))
+        }
+        ast::Security::Bearer { name, format } => Some(match format {
+          Some(format) => format!("@param {name} Bearer token ({format})"),
+          None => format!("@param {name} Bearer token"),
+        }),
+        ast::Security::OAuth2 { name, .. } => {
+          Some(format!("@param {name} OAuth2 client, see `{name}Client`"))
+        }
+      })
+      .chain((!schemes.is_empty()).then(|| {
+        "@param onUnauthorized Called after a request was rejected with a 401, \
+         see `UnauthorizedHook`"
+          .to_string()
+      }))
+      .chain(std::iter::once(
+        "@param defaults Options of every request, such as its `timeoutMs` or \
+         `retry` policy"
+          .to_string(),
+      ))
+      .collect::<Vec<_>>();
+    let emit_doc = |buffer: &mut Buffer<'src>| buffer.doc(params.join("\n"));
 
-      buffer.raw("fetch : typeof window . fetch ,");
+    buffer.doc(
+      "Client of the API, each instance has its own base URL, credentials \
+       and `fetch`",
+    );
+    buffer.raw("export class ApiClient");
+    buffer.braces(|buffer| {
+      buffer.raw("_config : _Config ;");
+      emit_doc(buffer);
+      buffer.raw("constructor");
+      buffer.parens(|buffer| ClientParams(&schemes, servers).emit(buffer));
+      buffer.braces(|buffer| {
+        buffer.raw("this . _config = { baseUrl , security :");
+        buffer.braces(|buffer| {
+          buffer.raw("credentials :");
+          Credentials(&schemes).emit(buffer);
+          buffer.comma();
+          if !schemes.is_empty() {
+            buffer.raw("onUnauthorized ,");
+          }
+        });
+        buffer.raw(", fetch , defaults , } ;");
+      });
+      for route in routes.iter() {
+        RouteMethods(route).emit(buffer);
+      }
     });
+
+    // created lazily, so that importing the module has no side effects
+    buffer.raw("let _client : ApiClient | undefined ;");
+    buffer.doc(
+      "The instance which the free functions call, created on first use \
+       unless `init` configured one",
+    );
+    buffer.raw("function _defaultClient ( ) : ApiClient");
     buffer.braces(|buffer| {
-      buffer.raw("_baseUrl = baseUrl ;");
+      buffer.raw("return _client ??= new ApiClient ( ) ;");
+    });
 
-      buffer.raw("_authHeaders =");
-      buffer.braces(|buffer| {
-        for scheme in self.values() {
-          buffer.string(scheme.key.clone());
-          buffer.colon();
-          buffer.identifier(scheme.name.clone());
+    emit_doc(buffer);
+    buffer.raw("export function init");
+    buffer.parens(|buffer| ClientParams(&schemes, servers).emit(buffer));
+    buffer.braces(|buffer| {
+      buffer.raw("_client = new ApiClient");
+      buffer.parens(|buffer| {
+        buffer.raw("baseUrl ,");
+        for scheme in schemes.values().filter(|s| !in_jar(s, cookies)) {
+          buffer.identifier(scheme.name().clone());
           buffer.comma();
         }
+        buffer.raw("fetch ,");
+        if !schemes.is_empty() {
+          buffer.raw("onUnauthorized ,");
+        }
+        buffer.raw("defaults ,");
       });
       buffer.semicolon();
-
-      buffer.raw("_fetch = fetch ;");
     });
+
+    for route in routes.iter() {
+      RouteFunctions(route).emit(buffer);
+    }
   }
 }
 
-impl<'src> Emit<'src> for ast::Routes<'src> {
+/// With `Cookies::Include`, cookie API keys come from the runtime's cookie
+/// jar instead
+fn in_jar(scheme: &ast::Security, cookies: Cookies) -> bool {
+  cookies == Cookies::Include
+    && matches!(
+      scheme,
+      ast::Security::ApiKey {
+        location: ast::ApiKeyLocation::Cookie,
+        ..
+      }
+    )
+}
+
+/// Parameters of the `ApiClient` constructor and of `init`
+struct ClientParams<'a, 'src>(&'a ast::SecuritySchemes<'src>, bool);
+impl<'a, 'src> Emit<'src> for ClientParams<'a, 'src> {
   fn emit(self, buffer: &mut Buffer<'src>) {
-    for route in self {
-      route.emit(buffer)
+    /*
+    baseUrl : string = #(servers? \(server0 ( ))) #(!servers? \("")) ,
+    #$each(scheme) \(#scheme ? : #(oauth2? \(OAuth2Client)) #(!oauth2? \(Credential < #type >)) ,)
+    fetch : typeof globalThis . fetch = _fetch ,
+    #(schemes? \(onUnauthorized ? : UnauthorizedHook ,))
+    defaults : RequestOptions = { } ,
+    */
+    let ClientParams(schemes, servers) = self;
+    let cookies = buffer.options().cookies;
+    buffer.raw("baseUrl : string =");
+    if servers {
+      buffer.raw("server0 ( )");
+    } else {
+      buffer.raw("\"\"");
+    }
+    buffer.comma();
+
+    // schemes left `undefined` are not used to authorize requests
+    for scheme in schemes.values().filter(|s| !in_jar(s, cookies)) {
+      buffer.identifier(scheme.name().clone());
+      buffer.question();
+      buffer.colon();
+      match scheme {
+        ast::Security::Basic { .. } => {
+          buffer.raw("Credential < { username : string , password : string } >")
+        }
+        ast::Security::OAuth2 { .. } => buffer.identifier("OAuth2Client"),
+        _ => buffer.raw("Credential < string >"),
+      }
+      buffer.comma();
+    }
+
+    buffer.raw("fetch : typeof globalThis . fetch = _fetch ,");
+    if !schemes.is_empty() {
+      buffer.raw("onUnauthorized ? : UnauthorizedHook ,");
     }
+    buffer.raw("defaults : RequestOptions = { } ,");
   }
 }
 
-impl<'src> Emit<'src> for ast::Route<'src> {
+/// Credentials of each security scheme, taken from the constructor parameters
+struct Credentials<'a, 'src>(&'a ast::SecuritySchemes<'src>);
+impl<'a, 'src> Emit<'src> for Credentials<'a, 'src> {
   fn emit(self, buffer: &mut Buffer<'src>) {
-    // TODO: timeouts
     /*
-    /**
-     * #description
-     */
-    export async function #name (
-      #(params? \(#name : #type , ))
-      #(body? \(body : #type , ))
-    ) : Promise < unknown > {
-
-      return await fetch ( url . toString ( ) , {
-        method : #method,
-        headers : {
-          ... _authHeaders ,
-          #(body.json? \("Content-Type" : "application/json"))
-        },
-        #(body? \(JSON . stringify ( body )))
-      } )
+    {
+      'scheme0': { in: 'header', name: 'key0', value: scheme0 },
+      'scheme1': { in: 'header', name: 'Authorization', value: scheme1, format: _basicAuth },
+      'scheme2': { in: 'header', name: 'Authorization', value: scheme2, format: _bearerAuth },
+      'scheme3': { in: 'oauth2', client: scheme3 },
+      #(cookies == include? \('scheme4': { in: 'jar' }))
+      ...
     }
     */
-    if let Some(desc) = self.description {
-      if let Some(body) = self
-        .request_body
-        .as_ref()
-        .filter(|b| b.mime_type == ast::MimeType::Multipart_FormData)
-      {
-        use std::fmt::Write;
-        let mut desc = format!("{desc}\n\nForm data:\n");
-        if let ast::TypeRef::Type(ast::Type::Object(props)) = &body.ty {
-          for (prop, ty) in props {
-            let mut buffer = Buffer::new();
-            ty.emit(&mut buffer);
-            writeln!(
-              desc,
-              "- {} ({})",
-              prop,
-              trim_in_place(String::from(buffer))
-            )
-            .unwrap();
-          }
-        }
-        buffer.doc(desc);
-      } else {
-        buffer.doc(desc.clone());
-      }
-    }
-    buffer.raw("export async function");
-    buffer.identifier(self.name.clone());
-    buffer.parens(|buffer| {
-      if !self.parameters.is_empty() {
-        buffer.raw("params :");
+    let cookies = buffer.options().cookies;
+    buffer.braces(|buffer| {
+      for scheme in self.0.values() {
+        let name = scheme.name().clone();
+        buffer.string(name.clone());
+        buffer.colon();
         buffer.braces(|buffer| {
-          for param in self.parameters.values() {
-            buffer.string(param.name.clone());
-            buffer.colon();
-            (&param.ty).emit(buffer);
+          let (location, key, format) = match scheme {
+            _ if in_jar(scheme, cookies) => {
+              buffer.raw("in : 'jar'");
+              return;
+            }
+            ast::Security::OAuth2 { .. } => {
+              buffer.raw("in : 'oauth2' , client :");
+              buffer.identifier(name);
+              return;
+            }
+            ast::Security::ApiKey { key, location, .. } => {
+              let location = match location {
+                ast::ApiKeyLocation::Header => "header",
+                ast::ApiKeyLocation::Query => "query",
+                ast::ApiKeyLocation::Cookie => "cookie",
+              };
+              (location, key.clone(), None)
+            }
+            ast::Security::Basic { .. } => {
+              ("header", "Authorization".into(), Some("_basicAuth"))
+            }
+            ast::Security::Bearer { .. } => {
+              ("header", "Authorization".into(), Some("_bearerAuth"))
+            }
+          };
+          buffer.raw("in :");
+          buffer.string(location);
+          buffer.comma();
+          buffer.raw("name :");
+          buffer.string(key);
+          buffer.comma();
+          buffer.raw("value :");
+          buffer.identifier(name);
+          if let Some(format) = format {
             buffer.comma();
+            buffer.raw("format :");
+            buffer.raw(format);
           }
         });
         buffer.comma();
       }
-      if let Some(body) = self.request_body.as_ref() {
-        buffer.identifier("body");
-        buffer.colon();
-        match body.mime_type {
-          ast::MimeType::Multipart_FormData => buffer.identifier("FormData"),
-          ast::MimeType::Application_Json => (&body.ty).emit(buffer),
-          ast::MimeType::Text_Plain => buffer.identifier("string"),
-          _ => {}
-        }
-        buffer.comma();
+    });
+  }
+}
+
+/// Creates the client which obtains the tokens of an OAuth2 scheme, using
+/// the flows the generated code supports
+struct OAuth2Factory<'a, 'src>(&'a Cow<'src, str>, &'a [ast::OAuth2Flow<'src>]);
+impl<'a, 'src> Emit<'src> for OAuth2Factory<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    /*
+    /**
+     * Creates the `#name` client for `ApiClient` and `init`
+     *
+     * #$each(scope) \(- `#scope`: #description)
+     */
+    export function #(name)Client ( options : OAuth2Options < #scopes > ) : OAuth2Client < #scopes > {
+      return new OAuth2Client ( {
+        #$each(supported flow) \(#flow : { authorizationUrl : #url , tokenUrl : #url , refreshUrl : #url , } ,)
+      } , options ) ;
+    }
+    */
+    let OAuth2Factory(name, flows) = self;
+    let mut scopes = IndexMap::new();
+    for flow in flows {
+      for (scope, desc) in flow.scopes.iter() {
+        scopes.entry(scope.clone()).or_insert(desc.clone());
+      }
+    }
+    let mut doc =
+      format!("Creates the `{name}` client for `ApiClient` and `init`");
+    if !scopes.is_empty() {
+      doc.push_str("\n\nScopes:");
+      for (scope, desc) in scopes.iter() {
+        doc.push_str(&format!("\n- `{scope}`: {desc}"));
+      }
+    }
+    let emit_scopes = |buffer: &mut Buffer<'src>| {
+      if scopes.is_empty() {
+        buffer.identifier("string");
+      } else {
+        ast::Type::Enum(scopes.keys().cloned().collect()).emit(buffer);
       }
+    };
+
+    buffer.doc(doc);
+    buffer.raw("export function");
+    buffer.identifier(format!("{name}Client"));
+    buffer.parens(|buffer| {
+      buffer.raw("options : OAuth2Options");
+      buffer.generics(emit_scopes);
     });
     buffer.colon();
-    buffer.identifier("Promise");
-    buffer.generics(|buffer| buffer.identifier("Response"));
-
+    buffer.identifier("OAuth2Client");
+    buffer.generics(emit_scopes);
     buffer.braces(|buffer| {
-      Url(self.endpoint.clone(), &self.parameters).emit(buffer);
-      buffer.raw("const response = await _fetch");
+      buffer.raw("return new OAuth2Client");
       buffer.parens(|buffer| {
-        buffer.raw("url . toString ( )");
-        buffer.comma();
         buffer.braces(|buffer| {
-          buffer.identifier("method");
-          buffer.colon();
-          buffer.string(self.method.as_str());
-          buffer.comma();
-
-          buffer.identifier("headers");
-          buffer.colon();
-          buffer.braces(|buffer| {
-            buffer.raw("... _authHeaders ,");
-            if let Some(mime_type) =
-              self.request_body.as_ref().map(|r| r.mime_type)
-            {
-              match mime_type {
-                ast::MimeType::Application_Json => {
-                  buffer.raw("'Content-Type' : 'application/json' ,");
-                }
-                ast::MimeType::Text_Plain => {
-                  buffer.raw("'Content-Type' : 'text/plain' ,");
-                }
-                // browser adds the header for FormData automatically
-                // application/x-www-form-urlencoded is only used in one request, and it has
-                // a formdata alternative, so it's still undecided how form-urlencoded in body
-                // should be handled, if at all.
-                ast::MimeType::Multipart_FormData
-                | ast::MimeType::Application_FormUrlEncoded => {}
+          for flow in flows {
+            // tokens of the other flows can still be passed in as options
+            let key = match flow.kind {
+              ast::OAuth2FlowKind::ClientCredentials => "clientCredentials",
+              ast::OAuth2FlowKind::AuthorizationCode => "authorizationCode",
+              ast::OAuth2FlowKind::Implicit | ast::OAuth2FlowKind::Password => {
+                continue
               }
-            }
-            for param in self
-              .parameters
-              .values()
-              .filter(|p| p.kind == ast::ParameterKind::Header)
-            {
-              buffer.string(param.name.clone());
-              buffer.colon();
-              buffer.identifier("params");
-              buffer.brackets(|buffer| buffer.string(param.name.clone()));
-              buffer.comma();
-            }
-          });
-          buffer.comma();
-
-          if let Some(body) = self.request_body.as_ref() {
-            buffer.identifier("body");
+            };
+            buffer.identifier(key);
             buffer.colon();
-            match body.mime_type {
-              ast::MimeType::Application_Json => {
-                buffer.raw("JSON . stringify ( body )")
-              }
-              ast::MimeType::Multipart_FormData | ast::MimeType::Text_Plain => {
-                buffer.identifier("body")
+            buffer.braces(|buffer| {
+              let urls = [
+                ("authorizationUrl", &flow.authorization_url),
+                ("tokenUrl", &flow.token_url),
+                ("refreshUrl", &flow.refresh_url),
+              ];
+              for (field, url) in urls {
+                if let Some(url) = url {
+                  buffer.identifier(field);
+                  buffer.colon();
+                  buffer.string(url.clone());
+                  buffer.comma();
+                }
               }
-              // we emit form-urlencoded into url
-              ast::MimeType::Application_FormUrlEncoded => {}
-            }
+            });
             buffer.comma();
           }
         });
+        buffer.raw(", options");
       });
       buffer.semicolon();
-      buffer.raw("return response ;");
     });
   }
 }
 
-struct Url<'a, 'src>(Cow<'src, str>, &'a ast::Parameters<'src>);
-impl<'a, 'src> Emit<'src> for Url<'a, 'src> {
+/// Parameters a route function takes, with `Cookies::Include` cookie
+/// parameters come from the cookie jar
+fn route_parameters<'a, 'src>(
+  route: &'a ast::Route<'src>,
+  cookies: Cookies,
+) -> Vec<&'a ast::Parameter<'src>> {
+  route
+    .parameters
+    .values()
+    .filter(|p| {
+      cookies == Cookies::Header || p.kind != ast::ParameterKind::Cookie
+    })
+    .collect()
+}
+
+/// Key of a parameter in the `params` object of a route function, prefixed
+/// with its location when another parameter has the same name
+fn param_key<'src>(
+  params: &ast::Parameters<'src>,
+  param: &ast::Parameter<'src>,
+) -> Cow<'src, str> {
+  if params
+    .keys()
+    .any(|(name, kind)| *name == param.name && *kind != param.kind)
+  {
+    format!("{}.{}", param.kind.as_str(), param.name).into()
+  } else {
+    param.name.clone()
+  }
+}
+
+struct RouteError<'a, 'src>(&'a ast::Route<'src>);
+impl<'a, 'src> Emit<'src> for RouteError<'a, 'src> {
   fn emit(self, buffer: &mut Buffer<'src>) {
-    let Url(endpoint, params) = self;
     /*
-    const url = new URL ( _baseUrl ) ;
-    url . pathname = endpoint
-      #$each(path param) \(. replace ( '{#name}' , params [ '#name' ] ))
-      ;
-    url . search = new URLSearchParams ( {
-      #$each(query array param) \(... ( params [ '#name' ] && Object . fromEntries ( params [ '#name' ] . map ( ( v , i ) => [ `#name[${i}]` , v ] ) ) ))
-      #$each(query param) \(... ( params [ '#name' ] && { '#name' : params [ '#name' ] } ))
-    } ) . toString ( ) ;
+    export type #(name)Error = ApiError & ( { status : #code , body : #type } | ... ) ;
     */
-    buffer.raw("const url = new URL");
-    buffer.parens(|buffer| buffer.identifier("_baseUrl"));
+    let RouteError(route) = self;
+    buffer.raw("export type");
+    buffer.identifier(format!("{}Error", route.name));
+    buffer.equals();
+    buffer.raw("ApiError &");
+    ErrorType(&route.responses).emit(buffer);
     buffer.semicolon();
+  }
+}
 
-    buffer.raw("url . pathname =");
-    buffer.string(endpoint.clone());
-    for param in params
-      .values()
-      .filter(|p| p.kind == ast::ParameterKind::Path)
-    {
-      buffer.raw(". replace");
-      buffer.parens(|buffer| {
-        let name = param.name.clone();
-        buffer.string(format!("{{{name}}}"));
-        buffer.comma();
-        buffer.identifier("params");
-        buffer.brackets(|buffer| buffer.string(name));
+/// Parameter list and return type of a route function, or of its `Raw`
+/// variant
+struct RouteSignature<'a, 'src>(&'a ast::Route<'src>, bool);
+impl<'a, 'src> Emit<'src> for RouteSignature<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    /*
+    ( #params ) : #return_type
+    */
+    let RouteSignature(route, raw) = self;
+    buffer.parens(|buffer| RouteParams(route).emit(buffer));
+    buffer.colon();
+    RouteReturnType(route, raw).emit(buffer);
+  }
+}
+
+/// Parameters of a route function
+struct RouteParams<'a, 'src>(&'a ast::Route<'src>);
+impl<'a, 'src> Emit<'src> for RouteParams<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    /*
+    #(params? \(params : { #name : #type , ... } , ))
+    #(body? \(body : #type , ))
+    options ? : RequestOptions ,
+    */
+    let RouteParams(route) = self;
+    let parameters = route_parameters(route, buffer.options().cookies);
+    if !parameters.is_empty() {
+      buffer.raw("params :");
+      buffer.braces(|buffer| {
+        for param in parameters.iter() {
+          buffer.string(param_key(&route.parameters, param));
+          buffer.colon();
+          (&param.ty).emit(buffer);
+          buffer.comma();
+        }
       });
+      buffer.comma();
     }
-    buffer.semicolon();
-    let has_query_params =
-      params.values().any(|p| p.kind == ast::ParameterKind::Query);
-    if has_query_params {
-      buffer.raw("url . search = new URLSearchParams");
+    if let Some(body) = route.request_body.as_ref() {
+      buffer.identifier("body");
+      buffer.colon();
+      BodyType(body).emit(buffer);
+      buffer.comma();
+    }
+    buffer.raw("options ? : RequestOptions ,");
+  }
+}
+
+/// `Promise < #(raw? \(Response)) #(errors == throw? \(#response)) #(errors == result? \(Result < #response , #(name)Error >)) >`
+struct RouteReturnType<'a, 'src>(&'a ast::Route<'src>, bool);
+impl<'a, 'src> Emit<'src> for RouteReturnType<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let RouteReturnType(route, raw) = self;
+    let errors = buffer.options().errors;
+    buffer.identifier("Promise");
+    buffer.generics(|buffer| match errors {
+      _ if raw => buffer.identifier("Response"),
+      Errors::Throw => ResponseType(&route.responses).emit(buffer),
+      Errors::Result => {
+        buffer.identifier("Result");
+        buffer.generics(|buffer| {
+          ResponseType(&route.responses).emit(buffer);
+          buffer.comma();
+          buffer.identifier(format!("{}Error", route.name));
+        });
+      }
+    });
+  }
+}
+
+/// Passes the parameters of a route function on
+struct RouteArguments<'a, 'src>(&'a ast::Route<'src>);
+impl<'a, 'src> Emit<'src> for RouteArguments<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    /*
+    #(params? \(params ,)) #(body? \(body ,)) options ,
+    */
+    let RouteArguments(route) = self;
+    let parameters = route_parameters(route, buffer.options().cookies);
+    if !parameters.is_empty() {
+      buffer.raw("params ,");
+    }
+    if route.request_body.is_some() {
+      buffer.raw("body ,");
+    }
+    buffer.raw("options ,");
+  }
+}
+
+/// Methods of `ApiClient` which send the request of a route
+struct RouteMethods<'a, 'src>(&'a ast::Route<'src>);
+impl<'a, 'src> Emit<'src> for RouteMethods<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    /*
+    /**
+     * #description
+     */
+    async #name #signature {
+      #(errors == throw? \(return this . _#name ( ( response ) => _unwrap ( response , #responses ) , #arguments ) as Promise < #response > ;))
+      #(errors == result? \(return this . _#name ( ( response ) => _result ( response , #responses ) , #arguments ) as Promise < Result < #response , #(name)Error > > ;))
+    }
+
+    /**
+     * Same as `#name`, but resolves to the raw `Response`
+     */
+    async #(name)Raw #raw_signature {
+      return this . _#name ( async ( response ) => response , #arguments ) ;
+    }
+
+    /**
+     * Sends the request of `#name`, the timeout covers reading the response with `read` as well
+     */
+    async _#name < T > ( read : ( response : Response ) => Promise < T > , #params ) : Promise < T > {
+      const { baseUrl , security , fetch , defaults } = this . _config ;
+      const { headers , ... settings } = _requestOptions ( defaults , options ) ;
+      #(body? \(const request = _encodeBody ( #kind , #media_type , body ) ;))
+      return _send ( settings , #idempotent , ( signal ) =>
+        _authorized ( security , [ #$each(requirement) \([ #$each(scheme) \(#scheme ,) ] ,) ] , ( auth ) => {
+          #url
+          return fetch ( url . toString ( ) , {
+            method : #method,
+            headers : {
+              ... auth . headers ,
+              #(body? \(... request . headers ,))
+              #(content? \('Accept' : '#media_types' ,))
+              #$each(header param) \(... _serializeHeader ( #param ) ,)
+              #(cookies == header? \(... _cookieHeader ( auth . cookies , [ #$each(cookie param) \(#param ,) ] )))
+              ... headers ,
+            },
+            #(cookies == include? \(credentials : 'include'))
+            #(body? \(body : request . body ,))
+            signal ,
+          } ) . catch ( _fetchFailed ) ;
+        } ) ,
+        read ,
+      ) ;
+    }
+    */
+    let RouteMethods(route) = self;
+    let cookies = buffer.options().cookies;
+    let parameters = route_parameters(route, cookies);
+    let raw_name = format!("{}Raw", route.name);
+    let send_name = format!("_{}", route.name);
+
+    if let Some(desc) = route.description.clone() {
+      buffer.doc(desc);
+    }
+    buffer.raw("async");
+    buffer.identifier(route.name.clone());
+    RouteSignature(route, false).emit(buffer);
+    buffer.braces(|buffer| {
+      buffer.raw("return this .");
+      buffer.identifier(send_name.clone());
       buffer.parens(|buffer| {
-        buffer.braces(|buffer| {
-          for param in params
-            .values()
-            .filter(|p| p.kind == ast::ParameterKind::Query)
-          {
-            buffer.triple_dot();
-            buffer.parens(|buffer| {
-              buffer.identifier("params");
-              buffer.brackets(|buffer| buffer.string(param.name.clone()));
-              buffer.and_and();
-              buffer.parens(|buffer| {
-                if is_array_type(&param.ty) {
-                  buffer.raw(format!("Object . fromEntries ( params [ '{0}' ] . map ( ( v , i ) => [ `{0}[${{i}}]` , v . toString ( ) ] ) )", param.name));
-                } else {
-                  buffer.raw(format!("{{ '{0}' : params [ '{0}' ] . toString ( ) }}", param.name));
-                }
-              });
+        match buffer.options().errors {
+          Errors::Throw => buffer.raw("( response ) => _unwrap"),
+          Errors::Result => buffer.raw("( response ) => _result"),
+        }
+        buffer.parens(|buffer| {
+          buffer.raw("response ,");
+          ResponseKinds(&route.responses).emit(buffer);
+        });
+        buffer.comma();
+        RouteArguments(route).emit(buffer);
+      });
+      buffer.raw("as");
+      RouteReturnType(route, false).emit(buffer);
+      buffer.semicolon();
+    });
+
+    buffer.doc(format!(
+      "Same as `{}`, but resolves to the raw `Response`",
+      route.name
+    ));
+    buffer.raw("async");
+    buffer.identifier(raw_name);
+    RouteSignature(route, true).emit(buffer);
+    buffer.braces(|buffer| {
+      buffer.raw("return this .");
+      buffer.identifier(send_name.clone());
+      buffer.parens(|buffer| {
+        buffer.raw("async ( response ) => response ,");
+        RouteArguments(route).emit(buffer);
+      });
+      buffer.semicolon();
+    });
+
+    buffer.doc(format!(
+      "Sends the request of `{}`, the timeout covers reading the response \
+       with `read` as well",
+      route.name
+    ));
+    buffer.raw("async");
+    buffer.identifier(send_name);
+    buffer.raw("< T >");
+    buffer.parens(|buffer| {
+      buffer.raw("read : ( response : Response ) => Promise < T > ,");
+      RouteParams(route).emit(buffer);
+    });
+    buffer.raw(": Promise < T >");
+    buffer.braces(|buffer| {
+      buffer.raw(
+        "const { baseUrl , security , fetch , defaults } = this . _config ;",
+      );
+      buffer.raw(
+        "const { headers , ... settings } = _requestOptions ( defaults , options ) ;",
+      );
+      if let Some(body) = route.request_body.as_ref() {
+        EncodeBody(body).emit(buffer);
+      }
+      // only idempotent operations are retried by default
+      buffer.raw("return _send ( settings ,");
+      buffer.raw(if route.method.is_idempotent() {
+        "true"
+      } else {
+        "false"
+      });
+      // credentials are resolved again for every attempt
+      buffer.raw(", ( signal ) => _authorized ( security ,");
+      buffer.brackets(|buffer| {
+        for requirement in route.security.iter() {
+          buffer.brackets(|buffer| {
+            for scheme in requirement.keys() {
+              buffer.string(scheme.clone());
+              buffer.comma();
+            }
+          });
+          buffer.comma();
+        }
+      });
+      buffer.raw(", ( auth ) =>");
+      buffer.braces(|buffer| {
+        Url(route.endpoint.clone(), &route.parameters, &route.servers)
+          .emit(buffer);
+        buffer.raw("return fetch");
+        buffer.parens(|buffer| {
+          buffer.raw("url . toString ( )");
+          buffer.comma();
+          buffer.braces(|buffer| {
+            buffer.identifier("method");
+            buffer.colon();
+            buffer.string(route.method.as_str());
+            buffer.comma();
+
+            buffer.identifier("headers");
+            buffer.colon();
+            buffer.braces(|buffer| {
+              buffer.raw("... auth . headers ,");
+              if route.request_body.is_some() {
+                buffer.raw("... request . headers ,");
+              }
+              if let Some(accept) = accept(&route.responses) {
+                buffer.string("Accept");
+                buffer.colon();
+                buffer.string(accept);
+                buffer.comma();
+              }
+              for param in route
+                .parameters
+                .values()
+                .filter(|p| p.kind == ast::ParameterKind::Header)
+              {
+                buffer.raw("... _serializeHeader");
+                buffer.parens(|buffer| {
+                  ParamValue(&route.parameters, param).emit(buffer)
+                });
+                buffer.comma();
+              }
+              if cookies == Cookies::Header {
+                buffer.raw("... _cookieHeader");
+                buffer.parens(|buffer| {
+                  buffer.raw("auth . cookies ,");
+                  buffer.brackets(|buffer| {
+                    for param in parameters
+                      .iter()
+                      .filter(|p| p.kind == ast::ParameterKind::Cookie)
+                    {
+                      ParamValue(&route.parameters, param).emit(buffer);
+                      buffer.comma();
+                    }
+                  });
+                });
+                buffer.comma();
+              }
+              buffer.raw("... headers ,");
             });
             buffer.comma();
-          }
-        })
+
+            if cookies == Cookies::Include {
+              buffer.raw("credentials : 'include' ,");
+            }
+
+            if route.request_body.is_some() {
+              buffer.raw("body : request . body ,");
+            }
+
+            buffer.raw("signal ,");
+          });
+        });
+        // only errors of `fetch` itself are retried
+        buffer.raw(". catch ( _fetchFailed )");
+        buffer.semicolon();
       });
-      buffer.dot();
-      buffer.identifier("toString");
-      buffer.parens0();
+      buffer.raw(") , read , ) ;");
+    });
+  }
+}
+
+/// Free functions of a route, which call the default `ApiClient`
+struct RouteFunctions<'a, 'src>(&'a ast::Route<'src>);
+impl<'a, 'src> Emit<'src> for RouteFunctions<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    /*
+    /**
+     * #description
+     */
+    export async function #name #signature {
+      return _defaultClient ( ) . #name #arguments ;
+    }
+
+    /**
+     * Same as `#name`, but resolves to the raw `Response`
+     */
+    export async function #(name)Raw #raw_signature {
+      return _defaultClient ( ) . #(name)Raw #arguments ;
+    }
+    */
+    let RouteFunctions(route) = self;
+    let raw_name = format!("{}Raw", route.name);
+    if let Some(desc) = route.description.clone() {
+      buffer.doc(desc);
+    }
+    buffer.raw("export async function");
+    buffer.identifier(route.name.clone());
+    RouteSignature(route, false).emit(buffer);
+    buffer.braces(|buffer| {
+      buffer.raw("return _defaultClient ( ) .");
+      buffer.identifier(route.name.clone());
+      buffer.parens(|buffer| RouteArguments(route).emit(buffer));
+      buffer.semicolon();
+    });
+
+    buffer.doc(format!(
+      "Same as `{}`, but resolves to the raw `Response`",
+      route.name
+    ));
+    buffer.raw("export async function");
+    buffer.identifier(raw_name.clone());
+    RouteSignature(route, true).emit(buffer);
+    buffer.braces(|buffer| {
+      buffer.raw("return _defaultClient ( ) .");
+      buffer.identifier(raw_name);
+      buffer.parens(|buffer| RouteArguments(route).emit(buffer));
       buffer.semicolon();
+    });
+  }
+}
+
+/// Union of the types of the alternative bodies of a response
+struct ResponseBody<'a, 'src>(&'a [ast::Content<'src>]);
+impl<'a, 'src> Emit<'src> for ResponseBody<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let ResponseBody(content) = self;
+    if content.is_empty() {
+      return buffer.identifier("undefined");
+    }
+    let mut types = vec![];
+    for content in content {
+      let mut inner = Buffer::with_options(buffer.options().clone());
+      ResponseContent(content).emit(&mut inner);
+      let ty = trim_in_place(String::from(inner));
+      if !types.contains(&ty) {
+        types.push(ty);
+      }
+    }
+    for (i, ty) in types.into_iter().enumerate() {
+      if i > 0 {
+        buffer.or();
+      }
+      buffer.raw(ty);
+    }
+  }
+}
+
+/// Type of a response body, which depends on its media type
+struct ResponseContent<'a, 'src>(&'a ast::Content<'src>);
+impl<'a, 'src> Emit<'src> for ResponseContent<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let ResponseContent(content) = self;
+    match content.media_type.kind() {
+      ast::MediaKind::Json => (&content.ty).emit(buffer),
+      ast::MediaKind::Text => buffer.identifier("string"),
+      ast::MediaKind::EventStream => {
+        buffer.raw("AsyncIterable < ServerSentEvent <");
+        if is_text_events(content) {
+          buffer.identifier("string");
+        } else {
+          (&content.ty).emit(buffer);
+        }
+        buffer.raw("> >");
+      }
+      ast::MediaKind::JsonLines => {
+        buffer.identifier("AsyncIterable");
+        buffer.generics(|buffer| (&content.ty).emit(buffer));
+      }
+      ast::MediaKind::FormUrlEncoded => buffer.identifier("URLSearchParams"),
+      ast::MediaKind::Multipart => buffer.identifier("FormData"),
+      ast::MediaKind::Binary => match buffer.options().binary {
+        Binary::Blob => buffer.identifier("Blob"),
+        Binary::ArrayBuffer => buffer.identifier("ArrayBuffer"),
+        Binary::Stream => buffer.raw("ReadableStream < Uint8Array >"),
+      },
+    }
+  }
+}
+
+/// Media types of all declared response bodies, in the order of preference
+fn accept(responses: &ast::Responses) -> Option<String> {
+  let mut media_types: Vec<String> = vec![];
+  for content in responses.iter().flat_map(|(_, res)| &res.content) {
+    let media_type = content.media_type.to_string();
+    if !media_types.contains(&media_type) {
+      media_types.push(media_type);
+    }
+  }
+  (!media_types.is_empty()).then(|| media_types.join(", "))
+}
+
+/// Event data is only parsed as JSON when its schema describes something other than a string
+fn is_text_events(content: &ast::Content) -> bool {
+  matches!(
+    content.ty,
+    ast::TypeRef::Type(ast::Type::String | ast::Type::Any)
+  )
+}
+
+/// Name of the `_ResponseKind` which the runtime uses to decode the body
+fn response_kind(content: &ast::Content, binary: Binary) -> &'static str {
+  match content.media_type.kind() {
+    ast::MediaKind::Json => "json",
+    ast::MediaKind::Text => "text",
+    ast::MediaKind::EventStream if is_text_events(content) => "events",
+    ast::MediaKind::EventStream => "jsonEvents",
+    ast::MediaKind::JsonLines => "jsonLines",
+    ast::MediaKind::FormUrlEncoded => "form",
+    ast::MediaKind::Multipart => "multipart",
+    ast::MediaKind::Binary => match binary {
+      Binary::Blob => "blob",
+      Binary::ArrayBuffer => "arrayBuffer",
+      Binary::Stream => "stream",
+    },
+  }
+}
+
+/// Union of the bodies of all success responses
+struct ResponseType<'a, 'src>(&'a ast::Responses<'src>);
+impl<'a, 'src> Emit<'src> for ResponseType<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let ResponseType(responses) = self;
+    /*
+    #(ok? \(#type | undefined | ...))
+    #(ok and all empty? \(void))
+    #(!ok? \(unknown))
+    #(with headers? \({ data : #type , headers : #headers } | ... | #fallback))
+    */
+    let ok = success_responses(responses);
+    if ok.is_empty() {
+      return buffer.identifier("unknown");
+    }
+    let with_headers = ok.iter().any(|(_, res)| !res.headers.is_empty());
+    let mut types = vec![];
+    let mut push = |inner: Buffer| {
+      let ty = trim_in_place(String::from(inner));
+      if !types.contains(&ty) {
+        types.push(ty);
+      }
+    };
+    for (code, res) in ok.iter() {
+      // `204 No Content` never has a body, even if one is declared
+      let body = match code {
+        ast::Code::Exact(204) => &[],
+        _ => &res.content[..],
+      };
+      let mut inner = Buffer::with_options(buffer.options().clone());
+      if with_headers {
+        inner.braces(|buffer| {
+          buffer.raw("data :");
+          ResponseBody(body).emit(buffer);
+          buffer.comma();
+          buffer.raw("headers :");
+          ast::Type::Object(res.headers.clone()).emit(buffer);
+        });
+      } else {
+        ResponseBody(body).emit(&mut inner);
+      }
+      push(inner);
+    }
+    // undeclared success statuses are wrapped as well, see `ResponseKinds`
+    if with_headers {
+      let default = responses.iter().find(|(c, _)| *c == ast::Code::Default);
+      let mut inner = Buffer::with_options(buffer.options().clone());
+      inner.braces(|buffer| {
+        buffer.raw("data :");
+        match default {
+          Some((_, res)) => ResponseBody(&res.content).emit(buffer),
+          None => buffer.identifier("unknown"),
+        }
+        buffer.comma();
+        buffer.raw("headers :");
+        let headers = default.map(|(_, res)| res.headers.clone());
+        ast::Type::Object(headers.unwrap_or_default()).emit(buffer);
+      });
+      push(inner);
+    }
+    if types == ["undefined"] {
+      return buffer.identifier("void");
+    }
+    for (i, ty) in types.into_iter().enumerate() {
+      if i > 0 {
+        buffer.or();
+      }
+      buffer.raw(ty);
+    }
+  }
+}
+
+/// Responses which may be returned as data, as opposed to an `ApiError`
+fn success_responses<'a, 'src>(
+  responses: &'a ast::Responses<'src>,
+) -> Vec<&'a (ast::Code, ast::Response<'src>)> {
+  let mut ok = responses
+    .iter()
+    .filter(|(code, _)| code.is_ok())
+    .collect::<Vec<_>>();
+  // without any success responses, `default` is the only one which may describe them
+  if ok.is_empty() {
+    ok.extend(responses.iter().find(|(c, _)| *c == ast::Code::Default));
+  }
+  ok
+}
+
+/// Name of the `_HeaderKind` which the runtime uses to parse a header value
+fn header_kind(ty: &ast::TypeRef) -> &'static str {
+  match ty {
+    ast::TypeRef::Type(ast::Type::Optional(ty)) => header_kind(ty),
+    ast::TypeRef::Type(ast::Type::Number) => "number",
+    ast::TypeRef::Type(ast::Type::Boolean) => "boolean",
+    ast::TypeRef::Type(ast::Type::Array(item)) => match header_kind(item) {
+      "number" => "number[]",
+      "boolean" => "boolean[]",
+      _ => "string[]",
+    },
+    _ => "string",
+  }
+}
+
+/// Union of all declared error responses, discriminated by `status`
+///
+/// Any status which is not declared is also rejected with an `ApiError`, so
+/// unless a range or `default` response covers it, the union ends with a
+/// member whose body is unknown.
+struct ErrorType<'a, 'src>(&'a ast::Responses<'src>);
+impl<'a, 'src> Emit<'src> for ErrorType<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let ErrorType(responses) = self;
+    /*
+    ( { status : #(exact? \(#code)) #(range or default? \(number)) , body : #type } | ...
+      #(no range or default? \(| { status : number , body : unknown })) )
+    */
+    let errors = responses
+      .iter()
+      .filter(|(c, _)| !c.is_ok())
+      .collect::<Vec<_>>();
+    let undeclared = !errors
+      .iter()
+      .any(|(c, _)| matches!(c, ast::Code::Range(..) | ast::Code::Default));
+    buffer.parens(|buffer| {
+      for (i, (code, res)) in errors.iter().enumerate() {
+        if i > 0 {
+          buffer.or();
+        }
+        buffer.braces(|buffer| {
+          buffer.raw("status :");
+          match code {
+            ast::Code::Exact(v) => buffer.raw(v.to_string()),
+            ast::Code::Range(..) | ast::Code::Default => {
+              buffer.identifier("number")
+            }
+          }
+          buffer.comma();
+          buffer.raw("body :");
+          ResponseBody(&res.content).emit(buffer);
+        });
+      }
+      if undeclared {
+        if !errors.is_empty() {
+          buffer.or();
+        }
+        buffer.raw("{ status : number , body : unknown }");
+      }
+    });
+  }
+}
+
+/// Table used by the runtime to decode the body of the response matching a status
+struct ResponseKinds<'a, 'src>(&'a ast::Responses<'src>);
+impl<'a, 'src> Emit<'src> for ResponseKinds<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let ResponseKinds(responses) = self;
+    /*
+    { '#code' : '#kind' , ... }
+    #(several media types? \({ '#code' : { kind : { '#media_type' : '#kind' , ... } } , ... }))
+    #(with headers? \({ '#code' : { kind : #kind , headers : { '#name' : '#header_kind' , ... } } , ... , 'default' : { headers : { } } }))
+    */
+    let binary = buffer.options().binary;
+    let ok = success_responses(responses);
+    let with_headers = ok.iter().any(|(_, res)| !res.headers.is_empty());
+    // the runtime picks the kind by the `Content-Type` of the response
+    let kind =
+      |buffer: &mut Buffer<'src>, content: &[ast::Content<'src>]| match content
+      {
+        [] => buffer.string("empty"),
+        [content] => buffer.string(response_kind(content, binary)),
+        content => buffer.braces(|buffer| {
+          for content in content {
+            buffer.string(content.media_type.to_string());
+            buffer.colon();
+            buffer.string(response_kind(content, binary));
+            buffer.comma();
+          }
+        }),
+      };
+    buffer.braces(|buffer| {
+      for entry @ (code, res) in responses.iter() {
+        buffer.string(code.to_string());
+        buffer.colon();
+        // `default` also covers undeclared success statuses
+        if with_headers && (ok.contains(&entry) || *code == ast::Code::Default)
+        {
+          buffer.braces(|buffer| {
+            buffer.raw("kind :");
+            kind(buffer, &res.content);
+            buffer.comma();
+            buffer.raw("headers :");
+            buffer.braces(|buffer| {
+              for (name, ty) in res.headers.iter() {
+                buffer.string(name.clone());
+                buffer.colon();
+                buffer.string(header_kind(ty));
+                buffer.comma();
+              }
+            });
+          });
+        } else if res.content.len() > 1 {
+          buffer.braces(|buffer| {
+            buffer.raw("kind :");
+            kind(buffer, &res.content);
+          });
+        } else {
+          kind(buffer, &res.content);
+        }
+        buffer.comma();
+      }
+      // so that every success response resolves to the same shape
+      if with_headers
+        && !responses.iter().any(|(c, _)| *c == ast::Code::Default)
+      {
+        buffer.raw("'default' : { headers : { } } ,");
+      }
+    });
+  }
+}
+
+/// Name of the `_BodyKind` which the runtime uses to encode the body
+fn body_kind(media_type: &ast::MediaType) -> &'static str {
+  match media_type.kind() {
+    ast::MediaKind::Json => "json",
+    ast::MediaKind::Text
+    | ast::MediaKind::EventStream
+    | ast::MediaKind::JsonLines => "text",
+    ast::MediaKind::Multipart => "multipart",
+    ast::MediaKind::FormUrlEncoded => "form",
+    ast::MediaKind::Binary => "binary",
+  }
+}
+
+struct BodyType<'a, 'src>(&'a ast::RequestBody<'src>);
+impl<'a, 'src> Emit<'src> for BodyType<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let BodyType(body) = self;
+    /*
+    #(single? \(#type))
+    #(multiple? \(( { contentType : '#media_type' , data : #type } | ... )))
+    */
+    let emit_type = |buffer: &mut Buffer<'src>,
+                     content: &ast::Content<'src>| {
+      match content.media_type.kind() {
+        ast::MediaKind::Text
+        | ast::MediaKind::EventStream
+        | ast::MediaKind::JsonLines => buffer.identifier("string"),
+        ast::MediaKind::Binary => {
+          buffer.raw("Blob | ArrayBuffer");
+          if buffer.options().target.has_byte_arrays() {
+            buffer.raw("| Uint8Array");
+          }
+        }
+        ast::MediaKind::Multipart => {
+          buffer.files(|buffer| (&content.ty).emit(buffer));
+          buffer.or();
+          buffer.identifier("FormData");
+        }
+        ast::MediaKind::Json | ast::MediaKind::FormUrlEncoded => {
+          (&content.ty).emit(buffer)
+        }
+      }
+    };
+    match &body.content[..] {
+      [content] => emit_type(buffer, content),
+      content => buffer.parens(|buffer| {
+        for (i, content) in content.iter().enumerate() {
+          if i > 0 {
+            buffer.or();
+          }
+          buffer.braces(|buffer| {
+            buffer.raw("contentType :");
+            buffer.string(content.media_type.to_string());
+            buffer.comma();
+            buffer.raw("data :");
+            emit_type(buffer, content);
+          });
+        }
+      }),
     }
   }
 }
 
-fn is_array_type(ty: &ast::TypeRef) -> bool {
-  match ty {
-    ast::TypeRef::Type(ast::Type::Optional(inner)) => {
-      matches!(&**inner, ast::TypeRef::Type(ast::Type::Array(..)))
-    }
-    ast::TypeRef::Type(ast::Type::Array(..)) => true,
-    _ => false,
+struct EncodeBody<'a, 'src>(&'a ast::RequestBody<'src>);
+impl<'a, 'src> Emit<'src> for EncodeBody<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let EncodeBody(body) = self;
+    /*
+    #(single? \(const request = _encodeBody ( '#kind' , '#media_type' , body #(encoding? \(, #encoding)) ) ;))
+    #(multiple? \(const request = _encodeBody ( ( { '#media_type' : '#kind' , ... } as const ) [ body . contentType ] , body . contentType , body . data
+      #(encoding? \(, ( { '#media_type' : #encoding , ... } as Record < string , Record < string , _Encoding > > ) [ body . contentType ])) ) ;))
+    */
+    buffer.raw("const request = _encodeBody");
+    buffer.parens(|buffer| match &body.content[..] {
+      [content] => {
+        buffer.string(body_kind(&content.media_type));
+        buffer.comma();
+        buffer.string(content.media_type.to_string());
+        buffer.comma();
+        buffer.identifier("body");
+        if !content.encoding.is_empty() {
+          buffer.comma();
+          EncodingValue(&content.encoding).emit(buffer);
+        }
+      }
+      content => {
+        buffer.parens(|buffer| {
+          buffer.braces(|buffer| {
+            for content in content {
+              buffer.string(content.media_type.to_string());
+              buffer.colon();
+              buffer.string(body_kind(&content.media_type));
+              buffer.comma();
+            }
+          });
+          buffer.raw("as const");
+        });
+        buffer.raw("[ body . contentType ] , body . contentType , body . data");
+        if content.iter().any(|c| !c.encoding.is_empty()) {
+          buffer.comma();
+          buffer.parens(|buffer| {
+            buffer.braces(|buffer| {
+              for content in content.iter().filter(|c| !c.encoding.is_empty()) {
+                buffer.string(content.media_type.to_string());
+                buffer.colon();
+                EncodingValue(&content.encoding).emit(buffer);
+                buffer.comma();
+              }
+            });
+            buffer.raw("as Record < string , Record < string , _Encoding > >");
+          });
+          buffer.raw("[ body . contentType ]");
+        }
+      }
+    });
+    buffer.semicolon();
+  }
+}
+
+/// `_Encoding` descriptors of body properties, keyed by property name
+struct EncodingValue<'a, 'src>(
+  &'a IndexMap<Cow<'src, str>, ast::Encoding<'src>>,
+);
+impl<'a, 'src> Emit<'src> for EncodingValue<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let EncodingValue(encoding) = self;
+    /*
+    { '#name' : { style : '#style' , explode : #explode , allowReserved : #allowReserved #(media_type? \(, contentType : '#media_type')) } , ... }
+    */
+    buffer.braces(|buffer| {
+      for (name, encoding) in encoding {
+        buffer.string(name.clone());
+        buffer.colon();
+        buffer.braces(|buffer| {
+          buffer.raw("style :");
+          buffer.string(encoding.style.as_str());
+          buffer.comma();
+          buffer.raw(format!("explode : {}", encoding.explode));
+          buffer.comma();
+          buffer.raw(format!("allowReserved : {}", encoding.allow_reserved));
+          if let Some(media_type) = encoding.media_type.as_ref() {
+            buffer.comma();
+            buffer.raw("contentType :");
+            buffer.string(media_type.to_string());
+          }
+        });
+        buffer.comma();
+      }
+    });
+  }
+}
+
+/// `_Param` descriptor used by the runtime serialization helpers
+struct ParamValue<'a, 'src>(
+  &'a ast::Parameters<'src>,
+  &'a ast::Parameter<'src>,
+);
+impl<'a, 'src> Emit<'src> for ParamValue<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let ParamValue(params, param) = self;
+    /*
+    { name : '#name' , value : params [ '#key' ] , style : '#style' , explode : #explode , allowReserved : #allowReserved #(media_type? \(, contentType : '#media_type')) }
+    */
+    buffer.braces(|buffer| {
+      buffer.raw("name :");
+      buffer.string(param.name.clone());
+      buffer.comma();
+      buffer.raw("value : params");
+      buffer.brackets(|buffer| buffer.string(param_key(params, param)));
+      buffer.comma();
+      buffer.raw("style :");
+      buffer.string(param.style.as_str());
+      buffer.comma();
+      buffer.raw(format!("explode : {}", param.explode));
+      buffer.comma();
+      buffer.raw(format!("allowReserved : {}", param.allow_reserved));
+      if let Some(media_type) = param.media_type.as_ref() {
+        buffer.comma();
+        buffer.raw("contentType :");
+        buffer.string(media_type.to_string());
+      }
+    });
+  }
+}
+
+struct Url<'a, 'src>(
+  Cow<'src, str>,
+  &'a ast::Parameters<'src>,
+  &'a ast::Servers<'src>,
+);
+impl<'a, 'src> Emit<'src> for Url<'a, 'src> {
+  fn emit(self, buffer: &mut Buffer<'src>) {
+    let Url(endpoint, params, servers) = self;
+    /*
+    const url = _url (
+      settings . baseUrl ?? #(servers? \(#server_url)) #(!servers? \(baseUrl)) ,
+      endpoint
+        #$each(path param) \(. replace ( '{#name}' , _serializePath ( #param ) ))
+    ) ;
+    url . search = _serializeQuery ( [
+      ... _authParams ( auth . query ) ,
+      #$each(query param) \(#param ,)
+    ] ) ;
+    */
+    buffer.raw("const url = _url");
+    buffer.parens(|buffer| {
+      // the first server of the operation, unless the caller picks another
+      buffer.raw("settings . baseUrl ??");
+      match servers.first() {
+        Some(server) => ServerUrl(server, false).emit(buffer),
+        None => buffer.identifier("baseUrl"),
+      }
+      buffer.comma();
+      buffer.string(endpoint.clone());
+      for param in params
+        .values()
+        .filter(|p| p.kind == ast::ParameterKind::Path)
+      {
+        buffer.raw(". replace");
+        buffer.parens(|buffer| {
+          let name = param.name.clone();
+          buffer.string(format!("{{{name}}}"));
+          buffer.comma();
+          buffer.raw("_serializePath");
+          buffer.parens(|buffer| ParamValue(params, param).emit(buffer));
+        });
+      }
+    });
+    buffer.semicolon();
+    // API keys may be sent in the query as well
+    buffer.raw("url . search = _serializeQuery");
+    buffer.parens(|buffer| {
+      buffer.brackets(|buffer| {
+        buffer.raw("... _authParams ( auth . query ) ,");
+        for param in params
+          .values()
+          .filter(|p| p.kind == ast::ParameterKind::Query)
+        {
+          ParamValue(params, param).emit(buffer);
+          buffer.comma();
+        }
+      })
+    });
+    buffer.semicolon();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+
+  macro_rules! type_emit_test {
+    ($name:ident, $ty:expr, $expected:literal) => {
+      #[test]
+      fn $name() {
+        let mut buffer = crate::emit::Buffer::new();
+        let ty = crate::ast::TypeRef::Type($ty);
+        ty.emit(&mut buffer);
+        assert_eq!(String::from(buffer).trim(), $expected);
+      }
+    };
+  }
+
+  macro_rules! ty {
+    ($inner:expr) => {
+      crate::ast::TypeRef::Type($inner)
+    };
+  }
+
+  macro_rules! name {
+    ($name:literal) => {
+      crate::ast::TypeRef::Ref($name.into())
+    };
+  }
+
+  use ast::Type;
+
+  type_emit_test!(any_type, Type::Any, "any");
+  type_emit_test!(number_type, Type::Number, "number");
+  type_emit_test!(string_type, Type::String, "string");
+  type_emit_test!(binary_type, Type::Binary, "string");
+  type_emit_test!(boolean_type, Type::Boolean, "boolean");
+  type_emit_test!(
+    enum_abc_type,
+    Type::Enum(vec!["a".into(), "b".into(), "c".into()]),
+    "( 'a' | 'b' | 'c' )"
+  );
+  type_emit_test!(
+    array_simple_type,
+    Type::Array(Box::new(ty!(Type::Any))),
+    "( any ) [ ]"
+  );
+  type_emit_test!(
+    array_nested_type,
+    Type::Array(Box::new(ty!(Type::Array(Box::new(ty!(Type::Any)))))),
+    "( ( any ) [ ] ) [ ]"
+  );
+  type_emit_test!(
+    object_type,
+    Type::Object(map! {
+      "a" => ty!(Type::Any),
+      "b" => ty!(Type::String),
+      "c" => ty!(Type::Number),
+      "d" => name!("Test")
+    }),
+    "( { 'a' : any , 'b' : string , 'c' : number , 'd' : Test , } )"
+  );
+  type_emit_test!(
+    array_object_type,
+    Type::Array(Box::new(ty!(Type::Object(map! {
+      "a" => ty!(Type::Any),
+      "b" => ty!(Type::String),
+      "c" => ty!(Type::Number),
+      "d" => name!("Test")
+    })))),
+    "( ( { 'a' : any , 'b' : string , 'c' : number , 'd' : Test , } ) ) [ ]"
+  );
+  type_emit_test!(
+    union_type,
+    Type::Union(vec![
+      ty!(Type::Number),
+      ty!(Type::String),
+      ty!(Type::Boolean),
+      name!("Test")
+    ]),
+    "( number | string | boolean | Test )"
+  );
+  type_emit_test!(
+    optional_type,
+    Type::Optional(Box::new(ty!(Type::String))),
+    "( string | undefined )"
+  );
+  type_emit_test!(
+    optional_ref,
+    Type::Optional(Box::new(name!("Test"))),
+    "( Test | undefined )"
+  );
+
+  #[test]
+  fn emit_type_decl() {
+    let mut buffer = Buffer::new();
+    ("Test".into(), ast::Type::Any).emit(&mut buffer);
+    assert_eq!(String::from(buffer).trim(), "export type Test = any ;");
+  }
+
+  macro_rules! scheme {
+    ($name:literal, $key:literal, $location:ident) => {
+      crate::ast::Security::ApiKey {
+        name: $name.into(),
+        key: $key.into(),
+        location: crate::ast::ApiKeyLocation::$location,
+      }
+    };
+  }
+
+  fn emit_client(
+    schemes: ast::SecuritySchemes<'_>,
+    routes: ast::Routes<'_>,
+    options: Options,
+  ) -> String {
+    let mut buffer = Buffer::with_options(options);
+    Client {
+      schemes,
+      routes,
+      servers: false,
+    }
+    .emit(&mut buffer);
+    String::from(buffer)
+  }
+
+  #[test]
+  fn emit_client_security_schemes() {
+    let out = emit_client(
+      map! {
+        "name0" => scheme!("name0", "header-key-0", Header),
+        "name1" => scheme!("name1", "header-key-1", Header),
+        "name2" => scheme!("name2", "cookie-key-2", Cookie)
+      },
+      vec![],
+      Options::default(),
+    );
+    let params = [
+      "baseUrl : string = \"\" ,",
+      "name0 ? : Credential < string > ,",
+      "name1 ? : Credential < string > ,",
+      "name2 ? : Credential < string > ,",
+      "fetch : typeof globalThis . fetch = _fetch ,",
+      "onUnauthorized ? : UnauthorizedHook ,",
+      "defaults : RequestOptions = { } ,",
+    ]
+    .join(" ");
+    let doc = "\n/**\n * @param onUnauthorized Called after a request was rejected with a 401, see `UnauthorizedHook`\n * @param defaults Options of every request, such as its `timeoutMs` or `retry` policy\n*/\n";
+    assert_eq!(
+      out.trim(),
+      [
+        "/**\n * Client of the API, each instance has its own base URL, credentials and `fetch`\n*/\n",
+        "export class ApiClient { _config : _Config ;",
+        doc,
+        "constructor (",
+        &params,
+        ") {",
+        "this . _config = { baseUrl , security : { credentials : {",
+        "'name0' : { in : 'header' , name : 'header-key-0' , value : name0 } ,",
+        "'name1' : { in : 'header' , name : 'header-key-1' , value : name1 } ,",
+        "'name2' : { in : 'cookie' , name : 'cookie-key-2' , value : name2 } ,",
+        "} , onUnauthorized , } , fetch , defaults , } ;",
+        "} }",
+        "let _client : ApiClient | undefined ;",
+        "\n/**\n * The instance which the free functions call, created on first use unless `init` configured one\n*/\n",
+        "function _defaultClient ( ) : ApiClient { return _client ??= new ApiClient ( ) ; }",
+        doc,
+        "export function init (",
+        &params,
+        ") {",
+        "_client = new ApiClient ( baseUrl , name0 , name1 , name2 , fetch , onUnauthorized , defaults , ) ;",
+        "}"
+      ]
+      .join(" ")
+    );
+  }
+
+  #[test]
+  fn emit_client_include_cookies() {
+    let out = emit_client(
+      map! {
+        "name0" => scheme!("name0", "header-key-0", Header),
+        "name1" => scheme!("name1", "cookie-key-1", Cookie)
+      },
+      vec![],
+      Options {
+        cookies: Cookies::Include,
+        ..Default::default()
+      },
+    );
+    assert!(out.contains(
+      "constructor ( baseUrl : string = \"\" , name0 ? : Credential < string > , fetch :"
+    ));
+    assert!(out.contains("'name1' : { in : 'jar' } ,"));
+    assert!(out.contains(
+      "_client = new ApiClient ( baseUrl , name0 , fetch , onUnauthorized , defaults , ) ;"
+    ));
+  }
+
+  #[test]
+  fn emit_client_without_schemes() {
+    let out = emit_client(IndexMap::new(), vec![], Options::default());
+    assert!(out.contains(
+      "constructor ( baseUrl : string = \"\" , fetch : typeof globalThis . fetch = _fetch , defaults : RequestOptions = { } , ) {"
+    ));
+    assert!(out.contains(
+      "this . _config = { baseUrl , security : { credentials : { } , } , fetch , defaults , } ;"
+    ));
+    assert!(out.contains("\n/**\n * @param defaults Options of every request, such as its `timeoutMs` or `retry` policy\n*/\n constructor"));
+    assert!(out
+      .contains("_client = new ApiClient ( baseUrl , fetch , defaults , ) ;"));
+  }
+
+  #[test]
+  fn emit_http_security_schemes() {
+    let out = emit_client(
+      map! {
+        "login" => ast::Security::Basic { name: "login".into() },
+        "token" => ast::Security::Bearer {
+          name: "token".into(),
+          format: Some("JWT".into()),
+        },
+        "key" => scheme!("key", "api_key", Query)
+      },
+      vec![],
+      Options::default(),
+    );
+    assert!(out.contains(
+      "\n/**\n * @param login Username and password\n * @param token Bearer token (JWT)\n * @param onUnauthorized Called after a request was rejected with a 401, see `UnauthorizedHook`\n * @param defaults Options of every request, such as its `timeoutMs` or `retry` policy\n*/\n"
+    ));
+    assert!(out.contains(
+      [
+        "login ? : Credential < { username : string , password : string } > ,",
+        "token ? : Credential < string > ,",
+        "key ? : Credential < string > ,",
+      ]
+      .join(" ")
+      .as_str()
+    ));
+    assert!(out.contains(
+      [
+        "'login' : { in : 'header' , name : 'Authorization' , value : login , format : _basicAuth } ,",
+        "'token' : { in : 'header' , name : 'Authorization' , value : token , format : _bearerAuth } ,",
+        "'key' : { in : 'query' , name : 'api_key' , value : key } ,",
+      ]
+      .join(" ")
+      .as_str()
+    ));
+  }
+
+  #[test]
+  fn emit_oauth2_factory() {
+    let mut buffer = Buffer::new();
+    let flows = vec![
+      ast::OAuth2Flow {
+        kind: ast::OAuth2FlowKind::AuthorizationCode,
+        authorization_url: Some("https://auth.example.com/authorize".into()),
+        token_url: Some("https://auth.example.com/token".into()),
+        refresh_url: None,
+        scopes: map! { "read" => "Read access".into() },
+      },
+      ast::OAuth2Flow {
+        kind: ast::OAuth2FlowKind::Implicit,
+        authorization_url: Some("https://auth.example.com/authorize".into()),
+        token_url: None,
+        refresh_url: None,
+        scopes: map! { "write" => "Write access".into() },
+      },
+    ];
+    OAuth2Factory(&"oauth".into(), &flows).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      [
+        "/**\n * Creates the `oauth` client for `ApiClient` and `init`\n *\n * Scopes:\n * - `read`: Read access\n * - `write`: Write access\n*/\n",
+        "export function oauthClient ( options : OAuth2Options < ( 'read' | 'write' ) > ) : OAuth2Client < ( 'read' | 'write' ) > {",
+        "return new OAuth2Client ( {",
+        "authorizationCode : { authorizationUrl : 'https://auth.example.com/authorize' , tokenUrl : 'https://auth.example.com/token' , } ,",
+        "} , options ) ;",
+        "}"
+      ]
+      .join(" ")
+    );
+  }
+
+  fn params<'src>(
+    params: impl IntoIterator<Item = ast::Parameter<'src>>,
+  ) -> ast::Parameters<'src> {
+    params
+      .into_iter()
+      .map(|p| ((p.name.clone(), p.kind), p))
+      .collect()
+  }
+
+  fn route<'src>(parameters: ast::Parameters<'src>) -> ast::Route<'src> {
+    ast::Route {
+      name: "test".into(),
+      endpoint: "/test".into(),
+      method: ast::Method::Get,
+      description: None,
+      parameters,
+      request_body: None,
+      responses: Default::default(),
+      security: vec![],
+      servers: vec![],
+    }
+  }
+
+  fn res(ty: Option<ast::TypeRef<'static>>) -> ast::Response<'static> {
+    ast::Response {
+      content: ty
+        .map(|ty| ast::Content {
+          media_type: "application/json".try_into().unwrap(),
+          ty,
+          encoding: IndexMap::new(),
+        })
+        .into_iter()
+        .collect(),
+      headers: IndexMap::new(),
+    }
+  }
+
+  fn emit_route(route: ast::Route<'_>, options: Options) -> String {
+    let mut buffer = Buffer::with_options(options);
+    RouteError(&route).emit(&mut buffer);
+    RouteMethods(&route).emit(&mut buffer);
+    RouteFunctions(&route).emit(&mut buffer);
+    String::from(buffer)
+  }
+
+  #[test]
+  fn emit_route_cookies() {
+    let route = route(params([param(
+      "session",
+      ast::ParameterKind::Cookie,
+      ast::ParameterStyle::Form,
+      true,
+      ty!(ast::Type::String),
+    )]));
+
+    let out = emit_route(route.clone(), Options::default());
+    assert!(out.contains("params : { 'session' : string , }"));
+    assert!(out.contains(
+      "return fetch ( url . toString ( ) , { method : 'get' , headers : { ... auth . headers ,"
+    ));
+    assert!(out.contains(
+      "... _cookieHeader ( auth . cookies , [ { name : 'session' , value : params [ 'session' ] , style : 'form' , explode : true , allowReserved : false } , ] ) ,"
+    ));
+    assert!(!out.contains("credentials :"));
+
+    let out = emit_route(
+      route,
+      Options {
+        cookies: Cookies::Include,
+        ..Default::default()
+      },
+    );
+    assert!(!out.contains("params"));
+    assert!(!out.contains("_cookieHeader"));
+    assert!(out.contains("credentials : 'include' ,"));
+  }
+
+  #[test]
+  fn emit_route_parameter_name_conflict() {
+    use ast::{ParameterKind::*, ParameterStyle::*};
+    let route = route(params([
+      param("id", Path, Simple, false, ty!(ast::Type::String)),
+      param("id", Query, Form, true, ty!(ast::Type::String)),
+      param("limit", Query, Form, true, ty!(ast::Type::Number)),
+    ]));
+
+    let out = emit_route(route, Options::default());
+    assert!(out.contains(
+      "params : { 'path.id' : string , 'query.id' : string , 'limit' : number , }"
+    ));
+    assert!(out.contains(
+      "_serializePath ( { name : 'id' , value : params [ 'path.id' ] ,"
+    ));
+    assert!(out.contains("{ name : 'id' , value : params [ 'query.id' ] ,"));
+    assert!(out.contains("{ name : 'limit' , value : params [ 'limit' ] ,"));
+  }
+
+  #[test]
+  fn emit_route_security() {
+    let secured = ast::Route {
+      security: vec![
+        map! { "key" => vec![], "oauth" => vec!["write".into()] },
+        IndexMap::new(),
+      ],
+      ..route(IndexMap::new())
+    };
+    let out = emit_route(secured, Options::default());
+    assert!(out.contains(
+      "_authorized ( security , [ [ 'key' , 'oauth' , ] , [ ] , ] , ( auth ) =>"
+    ));
+
+    let out = emit_route(route(IndexMap::new()), Options::default());
+    assert!(out.contains("_authorized ( security , [ ] , ( auth ) =>"));
+  }
+
+  #[test]
+  fn emit_route_options() {
+    let out = emit_route(route(IndexMap::new()), Options::default());
+    assert!(out.contains(
+      "async test ( options ? : RequestOptions , ) : Promise < unknown > { return this . _test ( ( response ) => _unwrap ( response , { } ) , options , ) as Promise < unknown > ; }"
+    ));
+    assert!(out.contains(
+      "async testRaw ( options ? : RequestOptions , ) : Promise < Response > { return this . _test ( async ( response ) => response , options , ) ; }"
+    ));
+    assert!(out.contains(
+      "async _test < T > ( read : ( response : Response ) => Promise < T > , options ? : RequestOptions , ) : Promise < T > {"
+    ));
+    assert!(out.contains(
+      "export async function test ( options ? : RequestOptions , ) : Promise < unknown > { return _defaultClient ( ) . test ( options , ) ; }"
+    ));
+    assert!(out.contains(
+      "const { headers , ... settings } = _requestOptions ( defaults , options ) ;"
+    ));
+    assert!(out.contains(
+      "return _send ( settings , true , ( signal ) => _authorized ( security , [ ] , ( auth ) => { const url"
+    ));
+    assert!(out.contains(
+      "... _cookieHeader ( auth . cookies , [ ] ) , ... headers , } , signal , } ) . catch ( _fetchFailed ) ; } ) , read , ) ; }"
+    ));
+    // only idempotent operations are retried by default
+    let post = ast::Route {
+      method: ast::Method::Post,
+      ..route(IndexMap::new())
+    };
+    let out = emit_route(post, Options::default());
+    assert!(out.contains("return _send ( settings , false , ( signal ) =>"));
+  }
+
+  fn param<'src>(
+    name: &'src str,
+    kind: ast::ParameterKind,
+    style: ast::ParameterStyle,
+    explode: bool,
+    ty: ast::TypeRef<'src>,
+  ) -> ast::Parameter<'src> {
+    ast::Parameter {
+      name: name.into(),
+      description: None,
+      kind,
+      ty,
+      style,
+      explode,
+      allow_reserved: false,
+      media_type: None,
+    }
+  }
+
+  #[test]
+  fn emit_url() {
+    use ast::{ParameterKind::*, ParameterStyle::*};
+    let mut buffer = Buffer::new();
+    let params = params([
+      param(
+        "a",
+        Path,
+        Simple,
+        false,
+        ast::TypeRef::Ref(Default::default()),
+      ),
+      param(
+        "b",
+        Path,
+        Matrix,
+        true,
+        ast::TypeRef::Ref(Default::default()),
+      ),
+      param("c", Query, Form, true, ty!(ast::Type::String)),
+      param(
+        "d",
+        Query,
+        Form,
+        true,
+        ty!(ast::Type::Optional(Box::new(ty!(ast::Type::String)))),
+      ),
+      param(
+        "e",
+        Query,
+        PipeDelimited,
+        false,
+        ty!(ast::Type::Array(Box::new(ty!(ast::Type::String)))),
+      ),
+      param(
+        "f",
+        Query,
+        DeepObject,
+        true,
+        ty!(ast::Type::Object(map! { "g" => ty!(ast::Type::String) })),
+      ),
+    ]);
+    Url("/endpoint/{a}/test/{b}".into(), &params, &vec![]).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      [
+        "const url = _url ( settings . baseUrl ?? baseUrl , '/endpoint/{a}/test/{b}'",
+        ". replace ( '{a}' , _serializePath ( { name : 'a' , value : params [ 'a' ] , style : 'simple' , explode : false , allowReserved : false } ) )",
+        ". replace ( '{b}' , _serializePath ( { name : 'b' , value : params [ 'b' ] , style : 'matrix' , explode : true , allowReserved : false } ) )",
+        ") ;",
+        "url . search = _serializeQuery ( [",
+        "... _authParams ( auth . query ) ,",
+        "{ name : 'c' , value : params [ 'c' ] , style : 'form' , explode : true , allowReserved : false } ,",
+        "{ name : 'd' , value : params [ 'd' ] , style : 'form' , explode : true , allowReserved : false } ,",
+        "{ name : 'e' , value : params [ 'e' ] , style : 'pipeDelimited' , explode : false , allowReserved : false } ,",
+        "{ name : 'f' , value : params [ 'f' ] , style : 'deepObject' , explode : true , allowReserved : false } ,",
+        "] ) ;"
+      ]
+      .join(" ")
+    );
+  }
+
+  fn server() -> ast::Server<'static> {
+    ast::Server {
+      url: "https://{region}.example.com/{version}".into(),
+      description: Some("Production".into()),
+      variables: map! {
+        "region" => ast::ServerVariable {
+          default: "eu".into(),
+          enumeration: vec!["eu".into(), "us".into()],
+          description: None,
+        },
+        "version" => ast::ServerVariable {
+          default: "v1".into(),
+          enumeration: vec![],
+          description: None,
+        }
+      },
+    }
+  }
+
+  #[test]
+  fn emit_servers() {
+    let mut buffer = Buffer::new();
+    let servers = vec![
+      server(),
+      ast::Server {
+        url: "http://localhost:8080".into(),
+        description: None,
+        variables: IndexMap::new(),
+      },
+    ];
+    ServerFunctions("server".into(), &servers).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      [
+        "/**\n * Production\n*/\n",
+        "export function server0 ( variables : { 'region' ? : ( 'eu' | 'us' ) , 'version' ? : string , } = { } ) : string {",
+        "return _serverUrl ( 'https://{region}.example.com/{version}' , { 'region' : 'eu' , 'version' : 'v1' , ... variables } ) ;",
+        "}",
+        "export function server1 ( ) : string { return 'http://localhost:8080' ; }"
+      ]
+      .join(" ")
+    );
+  }
+
+  #[test]
+  fn emit_url_server_override() {
+    let mut buffer = Buffer::new();
+    Url("/jobs".into(), &IndexMap::new(), &vec![server()]).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      [
+        "const url = _url ( settings . baseUrl ?? _serverUrl ( 'https://{region}.example.com/{version}' , { 'region' : 'eu' , 'version' : 'v1' , } ) , '/jobs' ) ;",
+        "url . search = _serializeQuery ( [ ... _authParams ( auth . query ) , ] ) ;",
+      ]
+      .join(" ")
+    );
   }
-}
 
-#[cfg(test)]
-mod tests {
-  use super::*;
-  use pretty_assertions::assert_eq;
+  #[test]
+  fn emit_url_path_encoding() {
+    use ast::{ParameterKind::*, ParameterStyle::*};
+    let mut buffer = Buffer::new();
+    let params = params([
+      param("id", Path, Simple, false, ty!(ast::Type::String)),
+      ast::Parameter {
+        allow_reserved: true,
+        ..param("path", Path, Simple, false, ty!(ast::Type::String))
+      },
+    ]);
+    Url("/files/{id}/{path}".into(), &params, &vec![]).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      [
+        "const url = _url ( settings . baseUrl ?? baseUrl , '/files/{id}/{path}'",
+        ". replace ( '{id}' , _serializePath ( { name : 'id' , value : params [ 'id' ] , style : 'simple' , explode : false , allowReserved : false } ) )",
+        ". replace ( '{path}' , _serializePath ( { name : 'path' , value : params [ 'path' ] , style : 'simple' , explode : false , allowReserved : true } ) )",
+        ") ;",
+        "url . search = _serializeQuery ( [ ... _authParams ( auth . query ) , ] ) ;",
+      ]
+      .join(" ")
+    );
+  }
 
-  macro_rules! type_emit_test {
-    ($name:ident, $ty:expr, $expected:literal) => {
-      #[test]
-      fn $name() {
-        let mut buffer = crate::emit::Buffer::new();
-        let ty = crate::ast::TypeRef::Type($ty);
-        ty.emit(&mut buffer);
-        assert_eq!(String::from(buffer).trim(), $expected);
-      }
+  #[test]
+  fn emit_content_param() {
+    let mut buffer = Buffer::new();
+    let param = ast::Parameter {
+      media_type: "application/json".try_into().ok(),
+      ..param(
+        "filter",
+        ast::ParameterKind::Query,
+        ast::ParameterStyle::Form,
+        true,
+        ty!(ast::Type::Any),
+      )
     };
+    ParamValue(&params([param.clone()]), &param).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "{ name : 'filter' , value : params [ 'filter' ] , style : 'form' , explode : true , allowReserved : false , contentType : 'application/json' }"
+    );
   }
 
-  macro_rules! ty {
-    ($inner:expr) => {
-      crate::ast::TypeRef::Type($inner)
+  #[test]
+  fn emit_escaped_strings() {
+    let mut buffer = Buffer::new();
+    let param = ast::Parameter {
+      media_type: r#"text/plain; x-it's="a\b""#.try_into().ok(),
+      ..param(
+        "it's",
+        ast::ParameterKind::Header,
+        ast::ParameterStyle::Simple,
+        false,
+        ty!(ast::Type::String),
+      )
     };
+    ParamValue(&params([param.clone()]), &param).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      r#"{ name : 'it\'s' , value : params [ 'it\'s' ] , style : 'simple' , explode : false , allowReserved : false , contentType : 'text/plain; x-it\'s="a\\b"' }"#
+    );
   }
 
-  macro_rules! name {
-    ($name:literal) => {
-      crate::ast::TypeRef::Ref($name.into())
+  #[test]
+  fn emit_multiple_body_types() {
+    let body = ast::RequestBody {
+      content: vec![
+        ast::Content {
+          media_type: "application/json".try_into().unwrap(),
+          ty: ty!(ast::Type::Object(map! { "file" => ty!(ast::Type::Binary) })),
+          encoding: IndexMap::new(),
+        },
+        ast::Content {
+          media_type: "multipart/form-data".try_into().unwrap(),
+          ty: ty!(ast::Type::Object(map! { "file" => ty!(ast::Type::Binary) })),
+          encoding: IndexMap::new(),
+        },
+      ],
     };
+
+    // only multipart bodies may contain files
+    let mut buffer = Buffer::new();
+    BodyType(&body).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "( { contentType : 'application/json' , data : ( { 'file' : string , } ) } | { contentType : 'multipart/form-data' , data : ( { 'file' : ( Blob | File ) , } ) | FormData } )"
+    );
+
+    let mut buffer = Buffer::new();
+    EncodeBody(&body).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "const request = _encodeBody ( ( { 'application/json' : 'json' , 'multipart/form-data' : 'multipart' , } as const ) [ body . contentType ] , body . contentType , body . data ) ;"
+    );
   }
 
-  use ast::Type;
+  #[test]
+  fn emit_target_types() {
+    let body = ast::RequestBody {
+      content: vec![ast::Content {
+        media_type: "application/octet-stream".try_into().unwrap(),
+        ty: ty!(ast::Type::Binary),
+        encoding: IndexMap::new(),
+      }],
+    };
+    let emit = |target: Target| {
+      let mut buffer = Buffer::with_options(Options {
+        target,
+        ..Default::default()
+      });
+      buffer.files(|buffer| ty!(ast::Type::Binary).emit(buffer));
+      BodyType(&body).emit(&mut buffer);
+      String::from(buffer)
+    };
 
-  type_emit_test!(any_type, Type::Any, "any");
-  type_emit_test!(number_type, Type::Number, "number");
-  type_emit_test!(string_type, Type::String, "string");
-  type_emit_test!(boolean_type, Type::Boolean, "boolean");
-  type_emit_test!(
-    enum_abc_type,
-    Type::Enum(vec!["a".into(), "b".into(), "c".into()]),
-    "( 'a' | 'b' | 'c' )"
-  );
-  type_emit_test!(
-    array_simple_type,
-    Type::Array(Box::new(ty!(Type::Any))),
-    "( any ) [ ]"
-  );
-  type_emit_test!(
-    array_nested_type,
-    Type::Array(Box::new(ty!(Type::Array(Box::new(ty!(Type::Any)))))),
-    "( ( any ) [ ] ) [ ]"
-  );
-  type_emit_test!(
-    object_type,
-    Type::Object(map! {
-      "a" => ty!(Type::Any),
-      "b" => ty!(Type::String),
-      "c" => ty!(Type::Number),
-      "d" => name!("Test")
-    }),
-    "( { 'a' : any , 'b' : string , 'c' : number , 'd' : Test , } )"
-  );
-  type_emit_test!(
-    array_object_type,
-    Type::Array(Box::new(ty!(Type::Object(map! {
-      "a" => ty!(Type::Any),
-      "b" => ty!(Type::String),
-      "c" => ty!(Type::Number),
-      "d" => name!("Test")
-    })))),
-    "( ( { 'a' : any , 'b' : string , 'c' : number , 'd' : Test , } ) ) [ ]"
-  );
-  type_emit_test!(
-    union_type,
-    Type::Union(vec![
-      ty!(Type::Number),
-      ty!(Type::String),
-      ty!(Type::Boolean),
-      name!("Test")
-    ]),
-    "( number | string | boolean | Test )"
-  );
-  type_emit_test!(
-    optional_type,
-    Type::Optional(Box::new(ty!(Type::String))),
-    "( string | undefined )"
-  );
-  type_emit_test!(
-    optional_ref,
-    Type::Optional(Box::new(name!("Test"))),
-    "( Test | undefined )"
-  );
+    assert_eq!(
+      emit(Target::Browser).trim(),
+      "( Blob | File ) Blob | ArrayBuffer"
+    );
+    assert_eq!(
+      emit(Target::Worker).trim(),
+      "( Blob | File ) Blob | ArrayBuffer"
+    );
+    assert_eq!(
+      emit(Target::Deno).trim(),
+      "( Blob | File ) Blob | ArrayBuffer | Uint8Array"
+    );
+    assert_eq!(
+      emit(Target::Node).trim(),
+      "Blob Blob | ArrayBuffer | Uint8Array"
+    );
+  }
 
   #[test]
-  fn emit_type_decl() {
+  fn emit_form_body() {
+    let body = ast::RequestBody {
+      content: vec![ast::Content {
+        media_type: "application/x-www-form-urlencoded".try_into().unwrap(),
+        ty: ty!(ast::Type::Any),
+        encoding: map! {
+          "tags" => ast::Encoding {
+            media_type: None,
+            style: ast::ParameterStyle::PipeDelimited,
+            explode: false,
+            allow_reserved: false,
+          }
+        },
+      }],
+    };
+
     let mut buffer = Buffer::new();
-    ("Test".into(), ast::Type::Any).emit(&mut buffer);
-    assert_eq!(String::from(buffer).trim(), "export type Test = any ;");
+    EncodeBody(&body).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "const request = _encodeBody ( 'form' , 'application/x-www-form-urlencoded' , body , { 'tags' : { style : 'pipeDelimited' , explode : false , allowReserved : false } , } ) ;"
+    );
   }
 
   #[test]
-  fn emit_security_schemes_init() {
+  fn emit_response_type() {
+    let responses: ast::Responses = vec![
+      (200u16.into(), res(Some(ty!(ast::Type::String)))),
+      (201u16.into(), res(Some(ty!(ast::Type::String)))),
+      (204u16.into(), res(None)),
+      (404u16.into(), res(Some(ty!(ast::Type::Number)))),
+    ];
     let mut buffer = Buffer::new();
-    macro_rules! scheme {
-      ($name:literal, $key: literal) => {
-        crate::ast::Security {
-          name: $name.into(),
-          key: $key.into(),
-        }
-      };
-    }
-    map! {
-      "name0" => scheme!("name0", "header-key-0"),
-      "name1" => scheme!("name1", "header-key-1")
-    }
-    .emit(&mut buffer);
+    ResponseType(&responses).emit(&mut buffer);
+    assert_eq!(String::from(buffer).trim(), "string | undefined");
+
+    let mut buffer = Buffer::new();
+    ResponseType(&ast::Responses::default()).emit(&mut buffer);
+    assert_eq!(String::from(buffer).trim(), "unknown");
+
+    let responses: ast::Responses = vec![
+      (404u16.into(), res(None)),
+      (ast::Code::Default, res(Some(ty!(ast::Type::Boolean)))),
+    ];
+    let mut buffer = Buffer::new();
+    ResponseType(&responses).emit(&mut buffer);
+    assert_eq!(String::from(buffer).trim(), "boolean");
+
+    let mut buffer = Buffer::new();
+    ResponseKinds(&responses).emit(&mut buffer);
     assert_eq!(
       String::from(buffer).trim(),
-      [
-        "let _baseUrl : string = \"\" ;",
-        "let _authHeaders : Record < string , string > = { } ;",
-        "let _fetch : typeof window . fetch = window . fetch ;",
-        "export function init (",
-        "baseUrl : string ,",
-        "name0 : string ,",
-        "name1 : string ,",
-        "fetch : typeof window . fetch ,",
-        ") {",
-        "_baseUrl = baseUrl ;",
-        "_authHeaders = {",
-        "'header-key-0' : name0 ,",
-        "'header-key-1' : name1 ,",
-        "} ;",
-        "_fetch = fetch ;",
-        "}"
-      ]
-      .join(" ")
+      "{ '404' : 'empty' , 'default' : 'json' , }"
     );
   }
 
   #[test]
-  fn emit_url() {
+  fn emit_error_type() {
+    let responses: ast::Responses = vec![
+      (200u16.into(), res(Some(ty!(ast::Type::String)))),
+      (404u16.into(), res(None)),
+      (409u16.into(), res(Some(ty!(ast::Type::Number)))),
+      (ast::Code::Range(5), res(Some(ty!(ast::Type::Boolean)))),
+      (ast::Code::Default, res(Some(ty!(ast::Type::String)))),
+    ];
     let mut buffer = Buffer::new();
-    let params = map! {
-      "a" => ast::Parameter {
-        name: "a".into(),
-        description: None,
-        kind: ast::ParameterKind::Path,
-        ty: ast::TypeRef::Ref(Default::default()),
-      },
-      "b" => ast::Parameter {
-        name: "b".into(),
-        description: None,
-        kind: ast::ParameterKind::Path,
-        ty: ast::TypeRef::Ref(Default::default()),
-      },
-      "c" => ast::Parameter {
-        name: "c".into(),
-        description: None,
-        kind: ast::ParameterKind::Query,
-        ty: ty!(ast::Type::String)
+    ErrorType(&responses).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "( { status : 404 , body : undefined } | { status : 409 , body : number } | { status : number , body : boolean } | { status : number , body : string } )"
+    );
+
+    // statuses which are not declared are still rejected
+    let responses: ast::Responses = vec![
+      (200u16.into(), res(Some(ty!(ast::Type::String)))),
+      (404u16.into(), res(None)),
+    ];
+    let mut buffer = Buffer::new();
+    ErrorType(&responses).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "( { status : 404 , body : undefined } | { status : number , body : unknown } )"
+    );
+
+    let mut buffer = Buffer::new();
+    ErrorType(&ast::Responses::default()).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "( { status : number , body : unknown } )"
+    );
+  }
+
+  #[test]
+  fn emit_route_errors() {
+    let out = emit_route(route(map! {}), Options::default());
+    assert!(out.contains("export type testError = ApiError &"));
+    assert!(out.contains(": Promise < unknown >"));
+    assert!(out.contains("( response ) => _unwrap ( response , { } )"));
+
+    let out = emit_route(
+      route(map! {}),
+      Options {
+        errors: Errors::Result,
+        ..Default::default()
       },
-      "d" => ast::Parameter {
-        name: "d".into(),
-        description: None,
-        kind: ast::ParameterKind::Query,
-        ty: ty!(ast::Type::Optional(Box::new(ty!(ast::Type::String))))
+    );
+    assert!(out.contains(": Promise < Result < unknown , testError > >"));
+    assert!(out.contains("( response ) => _result ( response , { } )"));
+  }
+
+  #[test]
+  fn emit_response_media_types() {
+    let content = |media_type: &'static str| ast::Response {
+      content: vec![ast::Content {
+        media_type: media_type.try_into().unwrap(),
+        ty: ty!(ast::Type::Any),
+        encoding: IndexMap::new(),
+      }],
+      headers: IndexMap::new(),
+    };
+    let responses: ast::Responses = vec![
+      (200u16.into(), content("text/csv")),
+      (206u16.into(), content("application/octet-stream")),
+      (204u16.into(), content("application/json")),
+    ];
+    let emit = |options: Options| {
+      let mut buffer = Buffer::with_options(options);
+      ResponseType(&responses).emit(&mut buffer);
+      ResponseKinds(&responses).emit(&mut buffer);
+      trim_in_place(String::from(buffer))
+    };
+    assert_eq!(
+      emit(Options::default()),
+      "string | Blob | undefined { '200' : 'text' , '206' : 'blob' , '204' : 'json' , }"
+    );
+    assert_eq!(
+      emit(Options {
+        binary: Binary::Stream,
+        ..Default::default()
+      }),
+      "string | ReadableStream < Uint8Array > | undefined { '200' : 'text' , '206' : 'stream' , '204' : 'json' , }"
+    );
+
+    let mut buffer = Buffer::new();
+    ResponseType(&vec![(204u16.into(), res(None))]).emit(&mut buffer);
+    assert_eq!(String::from(buffer).trim(), "void");
+  }
+
+  #[test]
+  fn emit_response_alternatives() {
+    let content = |media_type: &'static str, ty| ast::Content {
+      media_type: media_type.try_into().unwrap(),
+      ty,
+      encoding: IndexMap::new(),
+    };
+    let responses: ast::Responses = vec![
+      (
+        200u16.into(),
+        ast::Response {
+          content: vec![
+            content("application/json", ty!(ast::Type::Boolean)),
+            content("text/csv", ty!(ast::Type::Any)),
+            content("text/plain", ty!(ast::Type::Any)),
+          ],
+          headers: IndexMap::new(),
+        },
+      ),
+      (
+        404u16.into(),
+        ast::Response {
+          content: vec![content("text/plain", ty!(ast::Type::Any))],
+          headers: IndexMap::new(),
+        },
+      ),
+    ];
+    let mut buffer = Buffer::new();
+    ResponseType(&responses).emit(&mut buffer);
+    ResponseKinds(&responses).emit(&mut buffer);
+    assert_eq!(
+      trim_in_place(String::from(buffer)),
+      "boolean | string { '200' : { kind : { 'application/json' : 'json' , 'text/csv' : 'text' , 'text/plain' : 'text' , } } , '404' : 'text' , }"
+    );
+    assert_eq!(
+      accept(&responses).as_deref(),
+      Some("application/json, text/csv, text/plain")
+    );
+    assert_eq!(accept(&vec![(204u16.into(), res(None))]), None);
+  }
+
+  #[test]
+  fn emit_streaming_route() {
+    let content = |media_type: &'static str, ty| ast::Response {
+      content: vec![ast::Content {
+        media_type: media_type.try_into().unwrap(),
+        ty,
+        encoding: IndexMap::new(),
+      }],
+      headers: IndexMap::new(),
+    };
+    let mut route = route(map! {});
+    route.responses = vec![
+      (
+        200u16.into(),
+        content("text/event-stream", ty!(ast::Type::Number)),
+      ),
+      (
+        206u16.into(),
+        content("text/event-stream", ty!(ast::Type::Any)),
+      ),
+      (
+        ast::Code::Range(2),
+        content("application/x-ndjson", ty!(ast::Type::Boolean)),
+      ),
+    ];
+
+    let mut buffer = Buffer::new();
+    ResponseType(&route.responses).emit(&mut buffer);
+    ResponseKinds(&route.responses).emit(&mut buffer);
+    assert_eq!(
+      trim_in_place(String::from(buffer)),
+      "AsyncIterable < ServerSentEvent < number > > | AsyncIterable < ServerSentEvent < string > > | AsyncIterable < boolean > { '200' : 'jsonEvents' , '206' : 'events' , '2XX' : 'jsonLines' , }"
+    );
+
+    let out = emit_route(route, Options::default());
+    assert!(out
+      .contains("export async function test ( options ? : RequestOptions , )"));
+    assert!(out.contains("signal , } )"));
+  }
+
+  #[test]
+  fn emit_response_headers() {
+    let responses: ast::Responses = vec![
+      (
+        201u16.into(),
+        ast::Response {
+          headers: map! {
+            "Location" => ty!(ast::Type::String),
+            "X-Total-Count" => ty!(ast::Type::Optional(Box::new(ty!(ast::Type::Number))))
+          },
+          ..res(Some(ty!(ast::Type::Boolean)))
+        },
+      ),
+      (204u16.into(), res(None)),
+      (404u16.into(), res(None)),
+    ];
+    let mut buffer = Buffer::new();
+    ResponseType(&responses).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "{ data : boolean , headers : ( { 'Location' : string , 'X-Total-Count' ? : ( number | undefined ) , } ) } | { data : undefined , headers : ( { } ) } | { data : unknown , headers : ( { } ) }"
+    );
+
+    let mut buffer = Buffer::new();
+    ResponseKinds(&responses).emit(&mut buffer);
+    assert_eq!(
+      String::from(buffer).trim(),
+      "{ '201' : { kind : 'json' , headers : { 'Location' : 'string' , 'X-Total-Count' : 'number' , } } , '204' : { kind : 'empty' , headers : { } } , '404' : 'empty' , 'default' : { headers : { } } , }"
+    );
+
+    // a declared `default` is the fallback for undeclared success statuses
+    let mut responses = responses;
+    responses.push((ast::Code::Default, res(Some(ty!(ast::Type::String)))));
+    let mut buffer = Buffer::new();
+    ResponseType(&responses).emit(&mut buffer);
+    assert!(String::from(buffer)
+      .trim()
+      .ends_with("| { data : string , headers : ( { } ) }"));
+
+    let mut buffer = Buffer::new();
+    ResponseKinds(&responses).emit(&mut buffer);
+    assert!(String::from(buffer)
+      .trim()
+      .ends_with("'default' : { kind : 'json' , headers : { } } , }"));
+  }
+
+  #[test]
+  fn emit_runtime_features() {
+    let content = |media_type: &'static str| ast::Content {
+      media_type: media_type.try_into().unwrap(),
+      ty: ty!(ast::Type::Any),
+      encoding: IndexMap::new(),
+    };
+    let mut route = route(map! {});
+    route.request_body = Some(ast::RequestBody {
+      content: vec![content("multipart/form-data")],
+    });
+    route.responses = vec![(
+      200u16.into(),
+      ast::Response {
+        content: vec![content("application/x-ndjson")],
+        headers: IndexMap::new(),
       },
-      "e" => ast::Parameter {
-        name: "e".into(),
-        description: None,
-        kind: ast::ParameterKind::Query,
-        ty: ty!(ast::Type::Array(Box::new(ty!(ast::Type::String))))
+    )];
+    let ast = ast::Ast {
+      routes: vec![route],
+      types: IndexMap::new(),
+      schemes: map! {
+        "login" => ast::Security::Basic { name: "login".into() }
       },
-      "f" => ast::Parameter {
-        name: "f".into(),
-        description: None,
-        kind: ast::ParameterKind::Query,
-        ty: ty!(ast::Type::Optional(Box::new(ty!(ast::Type::Array(Box::new(ty!(ast::Type::String)))))))
-      }
+      security: vec![],
+      servers: vec![],
     };
-    Url("/endpoint/{a}/test/{b}".into(), &params).emit(&mut buffer);
     assert_eq!(
-      String::from(buffer).trim(),
-      [
-        "const url = new URL ( _baseUrl ) ;",
-        "url . pathname = '/endpoint/{a}/test/{b}'",
-        ". replace ( '{a}' , params [ 'a' ] )",
-        ". replace ( '{b}' , params [ 'b' ] )",
-        ";",
-        "url . search = new URLSearchParams ( {",
-        "... ( params [ 'c' ] && ( { 'c' : params [ 'c' ] . toString ( ) } ) ) ,",
-        "... ( params [ 'd' ] && ( { 'd' : params [ 'd' ] . toString ( ) } ) ) ,",
-        "... ( params [ 'e' ] && ( Object . fromEntries ( params [ 'e' ] . map ( ( v , i ) => [ `e[${i}]` , v . toString ( ) ] ) ) ) ) ,",
-        "... ( params [ 'f' ] && ( Object . fromEntries ( params [ 'f' ] . map ( ( v , i ) => [ `f[${i}]` , v . toString ( ) ] ) ) ) ) ,",
-        "} ) . toString ( ) ;"
-      ]
-      .join(" ")
+      runtime_features(&ast, &Options::default()),
+      ["basic", "body", "jsonLines", "multipartBody", "schemes"]
     );
+
+    // sections of features the spec doesn't use are left out
+    let out = runtime(&[]);
+    assert!(!out.contains("//#"));
+    assert!(out.contains("export class ApiError"));
+    assert!(!out.contains("_encodeBody"));
+    assert!(!out.contains("export type Credential"));
+    assert!(!out.contains("export type Result"));
+    assert!(!out.contains("export class OAuth2Client"));
+    assert!(!out.contains("_lines"));
+
+    let out = runtime(&["body", "jsonLines"]);
+    assert!(out.contains("function _encodeBody"));
+    assert!(!out.contains("function _part"));
+    assert!(!out.contains("case \"multipart\": {"));
+    assert!(out.contains("async function* _lines"));
+    assert!(out.contains("async function* _jsonLines"));
+    assert!(!out.contains("async function* _events"));
+    assert!(out.contains("return _jsonLines(response);"));
+    assert!(!out.contains("return _events(response, false);"));
+  }
+
+  #[test]
+  fn emit_declared_names() {
+    let ast = ast::Ast {
+      routes: vec![route(map! {})],
+      types: map! { "Job" => ast::Type::String },
+      schemes: IndexMap::new(),
+      security: vec![],
+      servers: vec![],
+    };
+    let names = declared_names(&ast)
+      .into_iter()
+      .map(|(name, _)| name)
+      .collect::<Vec<_>>();
+    // the runtime's names are reserved whichever features the spec uses
+    for name in [
+      "ApiError",
+      "Result",
+      "RequestOptions",
+      "OAuth2Client",
+      "ServerSentEvent",
+      "_lines",
+      "_delimiters",
+      "ApiClient",
+      "init",
+      "Job",
+      "test",
+      "testRaw",
+      "testError",
+      "_test",
+    ] {
+      assert!(names.contains(&name.into()), "{name}");
+    }
   }
 }
@@ -0,0 +1,950 @@
+type _Style =
+  | "matrix"
+  | "label"
+  | "form"
+  | "simple"
+  | "spaceDelimited"
+  | "pipeDelimited"
+  | "deepObject";
+
+interface _Param {
+  name: string;
+  value: unknown;
+  style: _Style;
+  explode: boolean;
+  allowReserved: boolean;
+  contentType?: string;
+}
+
+const _delimiters: Partial<Record<_Style, string>> = {
+  spaceDelimited: "%20",
+  pipeDelimited: "|",
+};
+
+function _encode(value: unknown, allowReserved: boolean): string {
+  const encoded = encodeURIComponent(String(value));
+  return allowReserved
+    ? encoded.replace(/%(3A|2F|3F|23|5B|5D|40|21|24|26|27|28|29|2A|2B|2C|3B|3D)/gi, decodeURIComponent)
+    : encoded;
+}
+
+function _entries(value: object): [string, unknown][] {
+  return Object.entries(value).filter(([, v]) => v !== undefined && v !== null);
+}
+
+/** Matches `application/json` as well as any `+json` structured syntax suffix. */
+function _isJson(contentType: string): boolean {
+  return /^[^/]+\/([^;]+\+)?json\s*(;|$)/i.test(contentType);
+}
+
+/** Parameters described using `content` are serialized as a single value of that media type. */
+function _content(param: _Param): _Param {
+  const { value, contentType } = param;
+  if (contentType === undefined || value === undefined || value === null) return param;
+  return { ...param, value: _isJson(contentType) ? JSON.stringify(value) : String(value) };
+}
+
+/** Serializes a `simple`, `label` or `matrix` style value, as used in paths and headers. */
+function _serializeValue(param: _Param, encode: (v: unknown) => string): string {
+  const { name, value, style, explode } = _content(param);
+  if (value === undefined || value === null) return "";
+  let items: string[];
+  let separator = ",";
+  if (Array.isArray(value)) {
+    items = value.map(encode);
+    if (explode && style === "matrix") return items.map((v) => `;${name}=${v}`).join("");
+    if (explode && style === "label") separator = ".";
+  } else if (typeof value === "object") {
+    const entries = _entries(value);
+    if (explode) {
+      if (style === "matrix") return entries.map(([k, v]) => `;${encode(k)}=${encode(v)}`).join("");
+      items = entries.map(([k, v]) => `${encode(k)}=${encode(v)}`);
+      if (style === "label") separator = ".";
+    } else {
+      items = entries.flat().map(encode);
+    }
+  } else {
+    items = [encode(value)];
+  }
+  const joined = items.join(separator);
+  switch (style) {
+    case "matrix":
+      return `;${name}=${joined}`;
+    case "label":
+      return `.${joined}`;
+    default:
+      return joined;
+  }
+}
+
+/**
+ * Path segments are percent-encoded, unless the parameter allows reserved characters.
+ * `.` and `..` segments are rejected, URL parsers resolve them even when percent-encoded.
+ */
+function _serializePath(param: _Param): string {
+  const value = _serializeValue(param, (v) => _encode(v, param.allowReserved));
+  if (value.split("/").some((segment) => /^(\.|%2e){1,2}$/i.test(segment))) {
+    throw new Error(`invalid value of the path parameter ${param.name}: ${value}`);
+  }
+  return value;
+}
+
+function _serializeHeader(param: _Param): Record<string, string> {
+  if (param.value === undefined || param.value === null) return {};
+  return { [param.name]: _serializeValue(param, String) };
+}
+
+/** Serializes `form`, `spaceDelimited`, `pipeDelimited` and `deepObject` style parameters, as used in queries and cookies. */
+function _serializeQuery(params: _Param[], separator = "&"): string {
+  const parts: string[] = [];
+  for (const param of params) {
+    const { name, value, style, explode, allowReserved } = _content(param);
+    if (value === undefined || value === null) continue;
+    const key = _encode(name, false);
+    const encode = (v: unknown) => _encode(v, allowReserved);
+    const delimiter = _delimiters[style] ?? ",";
+    if (Array.isArray(value)) {
+      if (explode) {
+        for (const v of value) parts.push(`${key}=${encode(v)}`);
+      } else {
+        parts.push(`${key}=${value.map(encode).join(delimiter)}`);
+      }
+    } else if (typeof value === "object") {
+      const entries = _entries(value);
+      if (style === "deepObject") {
+        for (const [k, v] of entries) parts.push(`${key}[${_encode(k, false)}]=${encode(v)}`);
+      } else if (explode) {
+        for (const [k, v] of entries) parts.push(`${_encode(k, false)}=${encode(v)}`);
+      } else {
+        parts.push(`${key}=${entries.flat().map(encode).join(delimiter)}`);
+      }
+    } else {
+      parts.push(`${key}=${encode(value)}`);
+    }
+  }
+  return parts.join(separator);
+}
+
+/** API keys are sent as plain `form` style values. */
+function _authParams(auth: Record<string, string>): _Param[] {
+  return Object.entries(auth).map(([name, value]): _Param => ({
+    name,
+    value,
+    style: "form",
+    explode: true,
+    allowReserved: false,
+  }));
+}
+
+function _cookieHeader(auth: Record<string, string>, params: _Param[]): Record<string, string> {
+  const cookies = _serializeQuery([..._authParams(auth), ...params], "; ");
+  return cookies ? { Cookie: cookies } : {};
+}
+
+//#if basic
+function _basicAuth({ username, password }: { username: string; password: string }): string {
+  // `btoa` only accepts latin-1, so the credentials are encoded as UTF-8 first
+  const bytes = new TextEncoder().encode(`${username}:${password}`);
+  return `Basic ${btoa(String.fromCharCode(...bytes))}`;
+}
+//#endif
+
+function _bearerAuth(token: string): string {
+  return `Bearer ${token}`;
+}
+
+//#if serverUrl
+/** Substitutes `{name}` placeholders of a server URL template. */
+function _serverUrl(template: string, variables: Record<string, string>): string {
+  return template.replace(/\{([^}]+)\}/g, (match, name: string) => variables[name] ?? match);
+}
+//#endif
+
+/** The current location in browsers and workers. Deno throws on access unless it was started with `--location`. */
+function _location(): string | undefined {
+  try {
+    return (globalThis as { location?: { href: string } }).location?.href;
+  } catch {
+    return undefined;
+  }
+}
+
+/** Appends a path to the base URL, keeping any path of the base. Relative bases resolve against the current location. */
+function _url(base: string, path: string): URL {
+  const url = new URL(base, _location());
+  url.pathname = url.pathname.replace(/\/+$/, "") + path;
+  return url;
+}
+
+//#if body
+type _BodyKind =
+  | "json"
+  | "text"
+//#if multipartBody
+  | "multipart"
+//#endif
+  | "form"
+  | "binary";
+
+/** Serialization of a single body property, which otherwise defaults to an exploded `form` style. */
+type _Encoding = Partial<Pick<_Param, "style" | "explode" | "allowReserved" | "contentType">>;
+
+interface _Body {
+  headers: Record<string, string>;
+  body?: BodyInit;
+}
+
+//#if multipartBody
+/** Files are sent as-is, or with the encoded type when they have none. Objects default to JSON parts and everything else to plain text. */
+function _part(value: unknown, contentType?: string): string | Blob {
+  if (value instanceof Blob) {
+    if (contentType === undefined || value.type !== "") return value;
+    return value instanceof File
+      ? new File([value], value.name, { type: contentType })
+      : new Blob([value], { type: contentType });
+  }
+  if (contentType === undefined) {
+    if (typeof value !== "object" || value === null) return String(value);
+    contentType = "application/json";
+  }
+  return new Blob([_isJson(contentType) ? JSON.stringify(value) : String(value)], { type: contentType });
+}
+//#endif
+
+function _encodeBody(
+  kind: _BodyKind,
+  contentType: string,
+  data: unknown,
+  encoding: Record<string, _Encoding> = {},
+): _Body {
+  switch (kind) {
+    case "json":
+      return { headers: { "Content-Type": contentType }, body: JSON.stringify(data) };
+    case "text":
+      return { headers: { "Content-Type": contentType }, body: String(data) };
+//#if multipartBody
+    // the runtime sets the content type along with the multipart boundary
+    case "multipart": {
+      if (data instanceof FormData) return { headers: {}, body: data };
+      const form = new FormData();
+      for (const [name, value] of _entries(data as object)) {
+        for (const v of Array.isArray(value) ? value : [value]) {
+          form.append(name, _part(v, encoding[name]?.contentType));
+        }
+      }
+      return { headers: {}, body: form };
+    }
+//#endif
+    case "binary":
+      return { headers: { "Content-Type": contentType }, body: data as Blob | ArrayBuffer | Uint8Array };
+    case "form": {
+      const params = _entries(data as object).map(
+        ([name, value]): _Param => ({
+          name,
+          value,
+          style: "form",
+          explode: true,
+          allowReserved: false,
+          ...encoding[name],
+        }),
+      );
+      // sent as-is, `URLSearchParams` would encode it again
+      return { headers: { "Content-Type": contentType }, body: _serializeQuery(params) };
+    }
+  }
+}
+//#endif
+
+/** Rejection of a non-success response, holding its status and parsed body. */
+export class ApiError extends Error {
+  readonly response: Response;
+  readonly status: number;
+  readonly body: unknown;
+
+  constructor(response: Response, status: number, body: unknown) {
+    super(`request failed with status ${status}`);
+    this.name = "ApiError";
+    this.response = response;
+    this.status = status;
+    this.body = body;
+  }
+}
+
+/** Rejects requests which did not receive a response within their `timeoutMs`. */
+export class TimeoutError extends Error {
+  readonly timeoutMs: number;
+
+  constructor(timeoutMs: number) {
+    super(`request timed out after ${timeoutMs}ms`);
+    this.name = "TimeoutError";
+    this.timeoutMs = timeoutMs;
+  }
+}
+
+type _Result<T, E> = { ok: true; data: T } | { ok: false; error: E };
+
+//#if result
+export type Result<T, E> = _Result<T, E>;
+//#endif
+
+/** Parses a JSON body, which may be empty. Error bodies fall back to plain text. */
+async function _json(response: Response): Promise<unknown> {
+  const text = await response.text();
+  if (!text) return undefined;
+  try {
+    return JSON.parse(text);
+  } catch (e) {
+    if (response.ok) throw e;
+    return text;
+  }
+}
+
+type _ResponseKind =
+  | "json"
+  | "text"
+  | "events"
+  | "jsonEvents"
+  | "jsonLines"
+  | "form"
+  | "multipart"
+  | "blob"
+  | "arrayBuffer"
+  | "stream"
+  | "empty";
+
+type _HeaderKind = "string" | "number" | "boolean" | "string[]" | "number[]" | "boolean[]";
+
+/** Responses with several media types are decoded according to their `Content-Type`, keyed by media type in the order of preference. */
+type _ResponseKinds = _ResponseKind | Record<string, _ResponseKind>;
+
+/**
+ * Responses which declare headers resolve to both the body and the parsed headers.
+ * Routes with such responses wrap every success response, unmatched ones fall back to `default`.
+ */
+type _ResponseSpec = _ResponseKind | { kind?: _ResponseKinds; headers?: Record<string, _HeaderKind> };
+
+/** Picks the kind of the media type matching the `Content-Type` of the response, `type/*` ranges match as well. */
+function _kind(response: Response, kinds: _ResponseKinds | undefined): _ResponseKind | undefined {
+  if (typeof kinds !== "object") return kinds;
+  const essence = (type: string) => type.split(";")[0].trim().toLowerCase();
+  const actual = essence(response.headers.get("Content-Type") ?? "");
+  const entries = Object.entries(kinds).map(([type, kind]) => [essence(type), kind] as const);
+  const match =
+    entries.find(([type]) => type === actual) ??
+    entries.find(([type]) => type === "*/*" || (type.endsWith("/*") && actual.startsWith(type.slice(0, -1))));
+  return (match ?? entries[0])?.[1];
+}
+
+function _headers(response: Response, kinds: Record<string, _HeaderKind>): Record<string, unknown> {
+  const headers: Record<string, unknown> = {};
+  for (const [name, kind] of Object.entries(kinds)) {
+    const value = response.headers.get(name);
+    if (value === null) continue;
+    const parse = (v: string) => (kind.startsWith("number") ? Number(v) : kind.startsWith("boolean") ? v === "true" : v);
+    headers[name] = kind.endsWith("[]") ? value.split(",").map((v) => parse(v.trim())) : parse(value);
+  }
+  return headers;
+}
+
+//#if events jsonEvents
+export interface ServerSentEvent<T> {
+  event: string;
+  data: T;
+  id?: string;
+  retry?: number;
+}
+//#endif
+
+//#if events jsonEvents jsonLines
+/** Splits a streamed body into lines. Breaking out of the iteration cancels the body. */
+async function* _lines(response: Response): AsyncGenerator<string> {
+  if (!response.body) return;
+  const reader = response.body.pipeThrough(new TextDecoderStream()).getReader();
+  let buffer = "";
+  try {
+    for (;;) {
+      const { done, value } = await reader.read();
+      if (done) break;
+      buffer += value;
+      // a trailing `\r` may be the first half of `\r\n`
+      const lines = buffer.split(/\r\n|\r(?!$)|\n/);
+      buffer = lines.pop() ?? "";
+      yield* lines;
+    }
+    if (buffer) yield buffer.replace(/\r$/, "");
+  } finally {
+    await reader.cancel().catch(() => {});
+  }
+}
+//#endif
+
+//#if events jsonEvents
+/** Parses `text/event-stream` frames. Event data is parsed as JSON when `json` is set. */
+async function* _events(response: Response, json: boolean): AsyncGenerator<ServerSentEvent<unknown>> {
+  let event = "";
+  let data: string[] = [];
+  let id: string | undefined;
+  let retry: number | undefined;
+  for await (const line of _lines(response)) {
+    if (line === "") {
+      if (data.length) {
+        const joined = data.join("\n");
+        yield { event: event || "message", data: json ? JSON.parse(joined) : joined, id, retry };
+      }
+      event = "";
+      data = [];
+      continue;
+    }
+    if (line.startsWith(":")) continue;
+    const colon = line.indexOf(":");
+    const field = colon === -1 ? line : line.slice(0, colon);
+    let value = colon === -1 ? "" : line.slice(colon + 1);
+    if (value.startsWith(" ")) value = value.slice(1);
+    switch (field) {
+      case "event":
+        event = value;
+        break;
+      case "data":
+        data.push(value);
+        break;
+      case "id":
+        id = value;
+        break;
+      case "retry":
+        if (/^\d+$/.test(value)) retry = Number(value);
+        break;
+    }
+  }
+}
+//#endif
+
+//#if jsonLines
+async function* _jsonLines(response: Response): AsyncGenerator<unknown> {
+  for await (const line of _lines(response)) {
+    if (line.trim()) yield JSON.parse(line);
+  }
+}
+//#endif
+
+/** Picks the declared response for a status: the exact code first, then its range, then `default`. */
+function _match<T>(responses: Record<string, T>, status: number): T | undefined {
+  return responses[status] ?? responses[`${Math.floor(status / 100)}XX`] ?? responses.default;
+}
+
+async function _decode(response: Response, kind: _ResponseKind | undefined): Promise<unknown> {
+  if (response.status === 204 || kind === "empty") {
+    // a body which is never read would otherwise hold on to the connection
+    await response.body?.cancel();
+    return undefined;
+  }
+  switch (kind) {
+    case "text":
+      return response.text();
+//#if events
+    case "events":
+      return _events(response, false);
+//#endif
+//#if jsonEvents
+    case "jsonEvents":
+      return _events(response, true);
+//#endif
+//#if jsonLines
+    case "jsonLines":
+      return _jsonLines(response);
+//#endif
+    case "form":
+      return new URLSearchParams(await response.text());
+    case "multipart":
+      return response.formData();
+    case "blob":
+      return response.blob();
+    case "arrayBuffer":
+      return response.arrayBuffer();
+    case "stream":
+      return response.body;
+    default:
+      return _json(response);
+  }
+}
+
+async function _result(
+  response: Response,
+  responses: Record<string, _ResponseSpec> = {},
+): Promise<_Result<unknown, ApiError>> {
+  const spec = _match(responses, response.status);
+  const { kind, headers } = typeof spec === "object" ? spec : { kind: spec, headers: undefined };
+  const body = await _decode(response, _kind(response, kind));
+  if (!response.ok) return { ok: false, error: new ApiError(response, response.status, body) };
+  return {
+    ok: true,
+    data: headers ? { data: body, headers: _headers(response, headers) } : body,
+  };
+}
+
+async function _unwrap(response: Response, responses: Record<string, _ResponseSpec> = {}): Promise<unknown> {
+  const result = await _result(response, responses);
+  if (!result.ok) throw result.error;
+  return result.data;
+}
+
+//#if oauth2
+export interface OAuth2Token {
+  accessToken: string;
+  refreshToken?: string;
+  /** Milliseconds since the epoch, tokens without it never expire. */
+  expiresAt?: number;
+}
+
+export interface OAuth2Options<Scope extends string = string> {
+  clientId: string;
+  clientSecret?: string;
+  scopes?: Scope[];
+  /** A previously obtained token, e.g. restored from storage. */
+  token?: OAuth2Token;
+  /** Called whenever a new token is obtained, e.g. to persist it. */
+  onToken?: (token: OAuth2Token) => void;
+  fetch?: typeof fetch;
+}
+
+interface _OAuth2Flows {
+  clientCredentials?: { tokenUrl: string; refreshUrl?: string };
+  authorizationCode?: { authorizationUrl: string; tokenUrl: string; refreshUrl?: string };
+}
+
+function _base64Url(bytes: Uint8Array): string {
+  return btoa(String.fromCharCode(...bytes))
+    .replace(/\+/g, "-")
+    .replace(/\//g, "_")
+    .replace(/=+$/, "");
+}
+
+/** Obtains, caches and refreshes the access tokens of an OAuth2 security scheme. */
+export class OAuth2Client<Scope extends string = string> {
+  readonly flows: _OAuth2Flows;
+  readonly options: OAuth2Options<Scope>;
+  token: OAuth2Token | undefined;
+  _pending: Promise<OAuth2Token> | undefined;
+
+  constructor(flows: _OAuth2Flows, options: OAuth2Options<Scope>) {
+    this.flows = flows;
+    this.options = options;
+    this.token = options.token;
+  }
+
+  /** Starts the authorization code flow with PKCE. The `verifier` has to be kept for `exchangeCode`. */
+  async authorizationUrl(redirectUri: string, state?: string): Promise<{ url: string; verifier: string }> {
+    const flow = this.flows.authorizationCode;
+    if (!flow) throw new Error("the authorization code flow is not supported");
+    const verifier = _base64Url(crypto.getRandomValues(new Uint8Array(32)));
+    const digest = await crypto.subtle.digest("SHA-256", new TextEncoder().encode(verifier));
+    const url = new URL(flow.authorizationUrl, _location());
+    const params = new URLSearchParams({
+      response_type: "code",
+      client_id: this.options.clientId,
+      redirect_uri: redirectUri,
+      code_challenge: _base64Url(new Uint8Array(digest)),
+      code_challenge_method: "S256",
+    });
+    if (this.options.scopes?.length) params.set("scope", this.options.scopes.join(" "));
+    if (state !== undefined) params.set("state", state);
+    url.search = params.toString();
+    return { url: url.toString(), verifier };
+  }
+
+  /** Completes the authorization code flow with the `code` passed to the redirect URI. */
+  async exchangeCode(code: string, redirectUri: string, verifier: string): Promise<OAuth2Token> {
+    const flow = this.flows.authorizationCode;
+    if (!flow) throw new Error("the authorization code flow is not supported");
+    return this._request(flow.tokenUrl, {
+      grant_type: "authorization_code",
+      code,
+      redirect_uri: redirectUri,
+      code_verifier: verifier,
+    });
+  }
+
+  /** Resolves to a valid access token, refreshing or requesting a new one once it expired. */
+  async accessToken(): Promise<string> {
+    const token = this.token;
+    // renew a little early, so the token doesn't expire in flight
+    if (token && (token.expiresAt === undefined || Date.now() < token.expiresAt - 30000)) return token.accessToken;
+    this._pending ??= this._renew().finally(() => {
+      this._pending = undefined;
+    });
+    return (await this._pending).accessToken;
+  }
+
+  /** Marks the access token as expired, e.g. after it was rejected. Returns whether a new one may be obtained. */
+  invalidate(): boolean {
+    const renewable = this.token?.refreshToken !== undefined || this.flows.clientCredentials !== undefined;
+    if (renewable && this.token) this.token = { ...this.token, expiresAt: 0 };
+    return renewable;
+  }
+
+  async _renew(): Promise<OAuth2Token> {
+    const { clientCredentials, authorizationCode } = this.flows;
+    const refreshToken = this.token?.refreshToken;
+    const flow = authorizationCode ?? clientCredentials;
+    if (refreshToken !== undefined && flow) {
+      try {
+        return await this._request(
+          flow.refreshUrl ?? flow.tokenUrl,
+          { grant_type: "refresh_token", refresh_token: refreshToken },
+          refreshToken,
+        );
+      } catch (e) {
+        if (!clientCredentials) throw e;
+      }
+    }
+    if (clientCredentials) {
+      const scopes = this.options.scopes ?? [];
+      return this._request(clientCredentials.tokenUrl, {
+        grant_type: "client_credentials",
+        ...(scopes.length ? { scope: scopes.join(" ") } : {}),
+      });
+    }
+    throw new Error("no OAuth2 token is available, complete the authorization code flow first");
+  }
+
+  async _request(url: string, params: Record<string, string>, refreshToken?: string): Promise<OAuth2Token> {
+    const { clientId, clientSecret } = this.options;
+    const body = new URLSearchParams({ ...params, client_id: clientId });
+    if (clientSecret !== undefined) body.set("client_secret", clientSecret);
+    const response = await (this.options.fetch ?? fetch)(url, {
+      method: "POST",
+      headers: { "Content-Type": "application/x-www-form-urlencoded", Accept: "application/json" },
+      body,
+    });
+    const data = await _json(response);
+    if (!response.ok) throw new ApiError(response, response.status, data);
+    const { access_token, refresh_token, expires_in } = data as {
+      access_token: string;
+      refresh_token?: string;
+      expires_in?: number;
+    };
+    // servers may omit the refresh token when it didn't change
+    this.token = {
+      accessToken: access_token,
+      refreshToken: refresh_token ?? refreshToken,
+      expiresAt: expires_in === undefined ? undefined : Date.now() + expires_in * 1000,
+    };
+    this.options.onToken?.(this.token);
+    return this.token;
+  }
+}
+//#endif
+
+//#if schemes
+/**
+ * A credential value, or a function that provides it before each request, for credentials that rotate.
+ * Functions may provide `undefined`, e.g. after signing out, so that the scheme counts as not configured.
+ */
+export type Credential<T> = T | (() => T | undefined | Promise<T | undefined>);
+
+/**
+ * Called after a request was rejected with a 401, with the security schemes it was sent with.
+ * Resolves to `true` to retry the request once with freshly provided credentials.
+ */
+export type UnauthorizedHook = (schemes: string[]) => boolean | Promise<boolean>;
+//#endif
+
+/** Access tokens of an OAuth2 security scheme, as obtained by an `OAuth2Client`. */
+interface _TokenSource {
+  accessToken(): Promise<string>;
+  invalidate(): boolean;
+}
+
+/** Credentials of a security scheme, schemes without a value are not configured. */
+type _Credential =
+  | {
+      in: "header" | "query" | "cookie";
+      name: string;
+      // the value of a `Credential`, or a function providing it
+      value: unknown;
+      // turns the provided value into the sent one, e.g. the `Authorization` header of basic authentication
+      format?: (value: any) => string;
+    }
+  | { in: "oauth2"; client: _TokenSource | undefined }
+  // cookies managed by the runtime's cookie jar
+  | { in: "jar" };
+
+interface _Security {
+  credentials: Record<string, _Credential>;
+  // see `UnauthorizedHook`
+  onUnauthorized?: (schemes: string[]) => boolean | Promise<boolean>;
+}
+
+/** Configuration of an `ApiClient`. */
+interface _Config {
+  baseUrl: string;
+  security: _Security;
+  fetch: typeof globalThis.fetch;
+  defaults: RequestOptions;
+}
+
+/** Looks the global `fetch` up when a request is sent, so it may be installed after the client is created. */
+function _fetch(...args: Parameters<typeof globalThis.fetch>): ReturnType<typeof globalThis.fetch> {
+  return globalThis.fetch(...args);
+}
+
+interface _Auth {
+  schemes: string[];
+  headers: Record<string, string>;
+  query: Record<string, string>;
+  cookies: Record<string, string>;
+  oauth2?: _TokenSource;
+}
+
+/** Provides the values of the credentials of `schemes`, or `undefined` unless all of them are configured. */
+async function _provide(
+  credentials: Record<string, _Credential>,
+  schemes: string[],
+): Promise<Record<string, unknown> | undefined> {
+  const values: Record<string, unknown> = {};
+  for (const scheme of schemes) {
+    const credential = credentials[scheme];
+    if (credential === undefined) return undefined;
+    if (credential.in === "jar") continue;
+    if (credential.in === "oauth2") {
+      if (credential.client === undefined) return undefined;
+      continue;
+    }
+    // providers are asked again for every request, and may have nothing to provide
+    const value = typeof credential.value === "function" ? await credential.value() : credential.value;
+    if (value === undefined) return undefined;
+    values[scheme] = value;
+  }
+  return values;
+}
+
+/**
+ * Resolves the credentials of the first alternative whose schemes are all configured.
+ * An empty alternative allows anonymous requests, which are only sent when no other alternative is configured,
+ * and no alternatives at all means the operation needs none.
+ */
+async function _auth(credentials: Record<string, _Credential>, requirements: string[][]): Promise<_Auth> {
+  const auth: _Auth = { schemes: [], headers: {}, query: {}, cookies: {} };
+  if (requirements.length === 0) return auth;
+  const ordered = [...requirements.filter((s) => s.length > 0), ...requirements.filter((s) => s.length === 0)];
+  for (const schemes of ordered) {
+    const values = await _provide(credentials, schemes);
+    if (values === undefined) continue;
+    auth.schemes = schemes;
+    for (const scheme of schemes) {
+      const credential = credentials[scheme];
+      if (credential.in === "oauth2") {
+        if (credential.client === undefined || auth.oauth2 !== undefined) continue;
+        auth.oauth2 = credential.client;
+        auth.headers.Authorization = _bearerAuth(await credential.client.accessToken());
+      } else if (credential.in !== "jar") {
+        const value = values[scheme];
+        const target = { header: auth.headers, query: auth.query, cookie: auth.cookies }[credential.in];
+        target[credential.name] = credential.format ? credential.format(value) : String(value);
+      }
+    }
+    return auth;
+  }
+  const expected = requirements.map((schemes) => schemes.join(" and ")).join(" or ");
+  throw new Error(`missing credentials, the operation requires ${expected}`);
+}
+
+/**
+ * Sends a request with the credentials it requires. After a 401, OAuth2 tokens are invalidated and the
+ * `onUnauthorized` hook is called, and the request is retried once if either allows it.
+ */
+async function _authorized(
+  security: _Security,
+  requirements: string[][],
+  send: (auth: _Auth) => Promise<Response>,
+): Promise<Response> {
+  const auth = await _auth(security.credentials, requirements);
+  const response = await send(auth);
+  if (response.status !== 401 || auth.schemes.length === 0) return response;
+  const renewable = auth.oauth2?.invalidate() ?? false;
+  const retry = (await security.onUnauthorized?.(auth.schemes)) ?? false;
+  if (!renewable && !retry) return response;
+  await response.body?.cancel();
+  return send(await _auth(security.credentials, requirements));
+}
+
+/** Options of a single request, which take precedence over the defaults of the client. */
+export interface RequestOptions {
+  /** Aborts the request, including the reading of its response body. */
+  signal?: AbortSignal;
+  /** Rejects with a `TimeoutError` unless the response arrives within this many milliseconds. */
+  timeoutMs?: number;
+  /** Sent in addition to the headers of the operation, replacing any of the same name. */
+  headers?: Record<string, string>;
+  /** Retries failed requests, every attempt has its own `timeoutMs`. */
+  retry?: RetryPolicy;
+  /** Replaces the base URL of the client and the servers of the operation, such as `server1({ region: "us" })`. */
+  baseUrl?: string;
+}
+
+/** When failed requests are retried. Only idempotent operations are retried, unless `nonIdempotent` is set. */
+export interface RetryPolicy {
+  /** Attempts including the first one, `1` disables retries. */
+  maxAttempts: number;
+  /** Upper bound of the delay before the first retry, which doubles with each further retry. Defaults to 500. */
+  baseDelayMs?: number;
+  /** Upper bound of any delay. Responses asking to wait longer with `Retry-After` are not retried. Defaults to 30000. */
+  maxDelayMs?: number;
+  /** Statuses which are retried, besides network errors and timeouts. Defaults to 408, 429, 502, 503 and 504. */
+  statuses?: number[];
+  /** Retries `POST` and `PATCH` operations as well, which may then take effect more than once. */
+  nonIdempotent?: boolean;
+}
+
+function _requestOptions(defaults: RequestOptions, options: RequestOptions = {}): RequestOptions {
+  return { ...defaults, ...options, headers: { ...defaults.headers, ...options.headers } };
+}
+
+/**
+ * Runs an attempt which the caller's `signal` aborts, and which is aborted with a `TimeoutError` after `timeoutMs`.
+ * Both apply from the start, even to steps which take no signal such as providing credentials, until `run` settles.
+ * Streamed bodies may be read for as long as needed, and the caller's `signal` aborts them until they end or are
+ * cancelled, as long as `run` reads the response `track` returns.
+ */
+async function _timeout<T>(
+  signal: AbortSignal | undefined,
+  timeoutMs: number | undefined,
+  run: (signal: AbortSignal | undefined, track: (response: Response) => Response) => Promise<T>,
+): Promise<T> {
+  if (signal === undefined && timeoutMs === undefined) return run(undefined, (response) => response);
+  signal?.throwIfAborted();
+  const controller = new AbortController();
+  const abort = () => controller.abort(signal?.reason);
+  signal?.addEventListener("abort", abort, { once: true });
+  const error = timeoutMs === undefined ? undefined : new TimeoutError(timeoutMs);
+  const timer = error && setTimeout(() => controller.abort(error), timeoutMs);
+  const aborted = new Promise<never>((_, reject) =>
+    controller.signal.addEventListener("abort", () => reject(controller.signal.reason), { once: true }),
+  );
+  // streamed bodies may still be aborted once the attempt settled
+  aborted.catch(() => {});
+  const release = () => signal?.removeEventListener("abort", abort);
+  let streaming = false;
+  const track = (response: Response) => {
+    if (signal === undefined || response.body === null) return response;
+    streaming = true;
+    return _withBody(response, _settling(response.body, release));
+  };
+  try {
+    return await Promise.race([run(controller.signal, track), aborted]);
+  } catch (e) {
+    // nothing is left to stream once the attempt failed
+    streaming = false;
+    throw error !== undefined && controller.signal.reason === error ? error : e;
+  } finally {
+    clearTimeout(timer);
+    if (!streaming) release();
+  }
+}
+
+/** Passes `body` through, and calls `settled` once it ended, failed or was cancelled. */
+function _settling(body: ReadableStream<Uint8Array>, settled: () => void): ReadableStream<Uint8Array> {
+  const reader = body.getReader();
+  return new ReadableStream({
+    async pull(controller) {
+      try {
+        const { done, value } = await reader.read();
+        if (!done) return controller.enqueue(value);
+        controller.close();
+      } catch (e) {
+        controller.error(e);
+      }
+      settled();
+    },
+    cancel(reason) {
+      settled();
+      return reader.cancel(reason);
+    },
+  });
+}
+
+/** A copy of `response` with another body, which keeps the URL it was fetched from. */
+function _withBody(response: Response, body: ReadableStream<Uint8Array>): Response {
+  const { status, statusText, headers, url, redirected } = response;
+  const copy = new Response(body, { status, statusText, headers });
+  return Object.defineProperties(copy, { url: { value: url }, redirected: { value: redirected } });
+}
+
+/** `Retry-After` holds either seconds or an HTTP date, such as `Wed, 21 Oct 2015 07:28:00 GMT`. */
+function _retryAfter(response: Response): number | undefined {
+  const value = response.headers.get("Retry-After")?.trim();
+  if (!value) return undefined;
+  if (/^\d+$/.test(value)) return Number(value) * 1000;
+  // `Date.parse` is lenient, and reads e.g. `1.5` as a date
+  if (!/^[A-Z][a-z]{2}, \d{2} [A-Z][a-z]{2} \d{4} \d{2}:\d{2}:\d{2} GMT$/.test(value)) return undefined;
+  const ms = Date.parse(value) - Date.now();
+  return isNaN(ms) ? undefined : Math.max(0, ms);
+}
+
+/** Errors `fetch` rejected with. Unlike errors while preparing a request, such as an invalid URL, they are retried. */
+const _fetchErrors = new WeakSet<object>();
+
+/** Marks an error of `fetch` as a network error, and rethrows it. */
+function _fetchFailed(error: unknown): never {
+  if (typeof error === "object" && error !== null) _fetchErrors.add(error);
+  throw error;
+}
+
+/** Waits for `ms`, unless the signal aborts first. */
+function _sleep(ms: number, signal: AbortSignal | undefined): Promise<void> {
+  return new Promise((resolve, reject) => {
+    if (signal?.aborted) return reject(signal.reason);
+    const abort = () => {
+      clearTimeout(timer);
+      reject(signal?.reason);
+    };
+    const timer = setTimeout(() => {
+      signal?.removeEventListener("abort", abort);
+      resolve();
+    }, ms);
+    signal?.addEventListener("abort", abort, { once: true });
+  });
+}
+
+/**
+ * Sends a request with a timeout per attempt, and retries it according to the retry policy.
+ * Attempts fail with a retryable status, a network error or a timeout, aborted requests are never retried.
+ * The timeout covers `read` as well, unless it leaves the body to be streamed.
+ */
+async function _send<T>(
+  options: RequestOptions,
+  idempotent: boolean,
+  send: (signal: AbortSignal | undefined) => Promise<Response>,
+  read: (response: Response) => Promise<T>,
+): Promise<T> {
+  const { signal, timeoutMs, retry } = options;
+  const policy = retry && (idempotent || retry.nonIdempotent) ? retry : undefined;
+  const {
+    maxAttempts = 1,
+    baseDelayMs = 500,
+    maxDelayMs = 30000,
+    statuses = [408, 429, 502, 503, 504],
+  }: Partial<RetryPolicy> = policy ?? {};
+  for (let i = 1; ; i++) {
+    // full jitter spreads out the retries of clients which failed at the same time
+    let delay = Math.random() * Math.min(maxDelayMs, baseDelayMs * 2 ** (i - 1));
+    try {
+      const result = await _timeout(signal, timeoutMs, async (signal, track) => {
+        const response = track(await send(signal));
+        if (i < maxAttempts && statuses.includes(response.status)) {
+          const after = _retryAfter(response);
+          if (after === undefined || after <= maxDelayMs) {
+            await response.body?.cancel();
+            return { retry: after ?? delay };
+          }
+        }
+        return { data: await read(response) };
+      });
+      if ("data" in result) return result.data;
+      delay = result.retry;
+    } catch (e) {
+      const retryable = e instanceof TimeoutError || (_fetchErrors.has(e as object) && !signal?.aborted);
+      if (i >= maxAttempts || !retryable) throw e;
+    }
+    await _sleep(delay, signal);
+  }
+}
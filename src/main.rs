@@ -1,7 +1,12 @@
 #![allow(clippy::upper_case_acronyms)]
 
 use {
-  oagen::{ast::AsAst, emit::emit},
+  oagen::{
+    ast::AsAst,
+    emit::{
+      emit_with, Binary, Cookies, Errors, Options as EmitOptions, Target,
+    },
+  },
   oapi3::OpenAPI,
   openapiv3 as oapi3,
   std::{
@@ -23,10 +28,33 @@ struct Options {
   input: PathBuf,
   #[structopt(parse(from_os_str))]
   output: PathBuf,
+  /// How cookies are sent: `header` builds a `Cookie` header (server runtimes),
+  /// `include` sends requests with `credentials: 'include'` (browsers)
+  #[structopt(long, default_value = "header")]
+  cookies: Cookies,
+  /// How non-success responses are surfaced: `throw` rejects with an `ApiError`,
+  /// `result` resolves to a `Result` holding either the data or the error
+  #[structopt(long, default_value = "throw")]
+  errors: Errors,
+  /// How binary response bodies are returned: `blob`, `arraybuffer`, or `stream`
+  /// for an unread `ReadableStream`
+  #[structopt(long, default_value = "blob")]
+  binary: Binary,
+  /// Runtime the generated code runs in: `browser`, `node`, `deno`, or `worker`.
+  /// `node` needs Node.js 20 or later and `@types/node` to type check
+  #[structopt(long, default_value = "browser")]
+  target: Target,
 }
 
 fn main() {
-  let Options { input, output } = Options::from_args();
+  let Options {
+    input,
+    output,
+    cookies,
+    errors,
+    binary,
+    target,
+  } = Options::from_args();
 
   if input.extension() != Some(OsStr::new("json")) {
     panic!("OpenAPI spec must be provided as .json");
@@ -43,5 +71,17 @@ fn main() {
       return;
     }
   };
-  fs::write(output, emit(ast)).expect("Failed to write to output file");
+  fs::write(
+    output,
+    emit_with(
+      ast,
+      EmitOptions {
+        cookies,
+        errors,
+        binary,
+        target,
+      },
+    ),
+  )
+  .expect("Failed to write to output file");
 }
@@ -27,7 +27,7 @@ impl<'src> Context<'src> {
     }
   }
   pub fn error(&mut self, e: ErrorKind) {
-    self.errors.push(Error::new(self.scope.clone(), e));
+    self.errors.push(Error::new(self.scope.snapshot(), e));
   }
 
   pub fn scope<S: Into<String>>(&self, name: S) -> error::ScopeGuard {
@@ -65,58 +65,199 @@ fn op_parse_desc(op: &oapi3::Operation) -> Option<Cow<'_, str>> {
     .map(Cow::from)
 }
 
-fn op_parse_params<'src>(
+/// Reusable objects which may be referenced from `#/components/<KIND>/<name>`.
+trait Component: Sized {
+  const KIND: &'static str;
+  fn lookup(
+    components: &oapi3::Components,
+  ) -> &IndexMap<String, oapi3::ReferenceOr<Self>>;
+}
+
+macro_rules! impl_component {
+  ($ty:ty, $kind:literal, $field:ident) => {
+    impl Component for $ty {
+      const KIND: &'static str = $kind;
+      fn lookup(
+        components: &oapi3::Components,
+      ) -> &IndexMap<String, oapi3::ReferenceOr<Self>> {
+        &components.$field
+      }
+    }
+  };
+}
+impl_component!(oapi3::Parameter, "parameters", parameters);
+impl_component!(oapi3::RequestBody, "requestBodies", request_bodies);
+impl_component!(oapi3::Response, "responses", responses);
+impl_component!(oapi3::Header, "headers", headers);
+
+/// Follows a chain of references into `components` until it reaches an item.
+fn resolve_component<'src, T: Component>(
   ctx: &mut Context<'src>,
-  op: &'src oapi3::Operation,
-) -> Option<ast::Parameters<'src>> {
-  let _scope = ctx.scope("parameters");
-  let mut params = ast::Parameters::<'src>::with_capacity(op.parameters.len());
-  for param in op.parameters.iter() {
-    if let Some(param) = param.as_item() {
-      use oapi3::Parameter::*;
-      let (kind, data) = match param {
-        Query { parameter_data, .. } => {
-          (ast::ParameterKind::Query, parameter_data)
-        }
-        Path { parameter_data, .. } => {
-          (ast::ParameterKind::Path, parameter_data)
-        }
-        Header { parameter_data, .. } => {
-          (ast::ParameterKind::Header, parameter_data)
-        }
-        Cookie { .. } => {
+  item: &'src oapi3::ReferenceOr<T>,
+) -> Option<&'src T> {
+  let mut visited = vec![];
+  let mut current = item;
+  loop {
+    let reference = match current {
+      oapi3::ReferenceOr::Item(item) => return Some(item),
+      oapi3::ReferenceOr::Reference { reference } => reference.as_str(),
+    };
+    if visited.contains(&reference) {
+      ctx.error(Error::cyclic_ref(reference.to_string()));
+      return None;
+    }
+    visited.push(reference);
+    current = match reference
+      .strip_prefix("#/components/")
+      .and_then(|r| r.strip_prefix(T::KIND))
+      .and_then(|r| r.strip_prefix('/'))
+      .zip(ctx.components)
+      .and_then(|(name, components)| T::lookup(components).get(name))
+    {
+      Some(next) => next,
+      None => {
+        ctx.error(Error::unresolved_ref(reference.to_string()));
+        return None;
+      }
+    };
+  }
+}
+
+fn query_style(style: &oapi3::QueryStyle) -> ast::ParameterStyle {
+  match style {
+    oapi3::QueryStyle::Form => ast::ParameterStyle::Form,
+    oapi3::QueryStyle::SpaceDelimited => ast::ParameterStyle::SpaceDelimited,
+    oapi3::QueryStyle::PipeDelimited => ast::ParameterStyle::PipeDelimited,
+    oapi3::QueryStyle::DeepObject => ast::ParameterStyle::DeepObject,
+  }
+}
+
+fn parse_param<'src>(
+  ctx: &mut Context<'src>,
+  param: &'src oapi3::ReferenceOr<oapi3::Parameter>,
+) -> Option<ast::Parameter<'src>> {
+  use oapi3::Parameter::*;
+  let (kind, data, style, allow_reserved) = match resolve_component(ctx, param)?
+  {
+    Query {
+      parameter_data,
+      style,
+      allow_reserved,
+      ..
+    } => (
+      ast::ParameterKind::Query,
+      parameter_data,
+      query_style(style),
+      *allow_reserved,
+    ),
+    Path {
+      parameter_data,
+      style,
+    } => {
+      let style = match style {
+        oapi3::PathStyle::Matrix => ast::ParameterStyle::Matrix,
+        oapi3::PathStyle::Label => ast::ParameterStyle::Label,
+        oapi3::PathStyle::Simple => ast::ParameterStyle::Simple,
+      };
+      (ast::ParameterKind::Path, parameter_data, style, false)
+    }
+    Header { parameter_data, .. } => (
+      ast::ParameterKind::Header,
+      parameter_data,
+      ast::ParameterStyle::Simple,
+      false,
+    ),
+    Cookie { parameter_data, .. } => (
+      ast::ParameterKind::Cookie,
+      parameter_data,
+      ast::ParameterStyle::Form,
+      false,
+    ),
+  };
+  let (media_type, schema) = match &data.format {
+    oapi3::ParameterSchemaOrContent::Schema(schema) => (None, Some(schema)),
+    oapi3::ParameterSchemaOrContent::Content(content) => {
+      let (mime, media) = match content.first() {
+        Some(v) if content.len() == 1 => v,
+        _ => {
+          ctx.error(Error::generic(format!(
+            "`content` of parameter `{}` must contain exactly one media type",
+            data.name
+          )));
           return None;
         }
       };
-      let ty = resolve_type(
-        ctx,
-        Some(data.name.as_str()),
-        match data.format {
-          oapi3::ParameterSchemaOrContent::Schema(ref s) => s,
-          oapi3::ParameterSchemaOrContent::Content(_) => {
-            return None;
-          }
-        },
-      )?;
-      // TODO: validate types here
-      // - cannot be a TypeRef::Ref
-      // - inner can only be String, Number, Boolean, single-level Object, Array + any of those in Optional
-      let param = ast::Parameter {
-        name: data.name.as_str().into(),
-        description: data.description.as_ref().map(|v| v.as_str().into()),
-        kind,
-        ty: if data.required {
-          ty
-        } else {
-          ast::TypeRef::Type(ast::Type::Optional(Box::new(ty)))
-        },
+      let media_type = match mime.as_str().try_into() {
+        Ok(v) => v,
+        Err(..) => {
+          ctx.error(Error::invalid_value("media type", mime.to_string()));
+          return None;
+        }
       };
-      params.insert(data.name.as_str().into(), param);
+      (Some(media_type), media.schema.as_ref())
+    }
+  };
+  let ty = match schema {
+    Some(schema) => resolve_type(ctx, Some(data.name.as_str()), schema)?,
+    None => ast::TypeRef::Type(ast::Type::Any),
+  };
+  // TODO: validate types here
+  // - cannot be a TypeRef::Ref
+  // - inner can only be String, Number, Boolean, single-level Object, Array + any of those in Optional
+  Some(ast::Parameter {
+    name: data.name.as_str().into(),
+    description: data.description.as_ref().map(|v| v.as_str().into()),
+    kind,
+    ty: if data.required {
+      ty
     } else {
-      return None;
+      ast::TypeRef::Type(ast::Type::Optional(Box::new(ty)))
+    },
+    style,
+    // only `form` explodes by default
+    explode: data.explode.unwrap_or(style == ast::ParameterStyle::Form),
+    allow_reserved,
+    media_type,
+  })
+}
+
+/// Parameters declared on the path item apply to all of its operations,
+/// but an operation may override them by declaring a parameter with the same
+/// name and location.
+fn op_parse_params<'src>(
+  ctx: &mut Context<'src>,
+  item: &'src oapi3::PathItem,
+  op: &'src oapi3::Operation,
+) -> Option<ast::Parameters<'src>> {
+  let _scope = ctx.scope("parameters");
+  let mut params = ast::Parameters::<'src>::with_capacity(
+    item.parameters.len() + op.parameters.len(),
+  );
+  let mut duplicates = vec![];
+  // whether the parameter at the same index in `params` may still be overridden
+  let mut overridable = Vec::with_capacity(params.capacity());
+  for (i, param) in item.parameters.iter().chain(&op.parameters).enumerate() {
+    let param = parse_param(ctx, param)?;
+    let inherited = i < item.parameters.len();
+    let key = (param.name.clone(), param.kind);
+    match params.get_index_of(&key) {
+      Some(index) if overridable[index] && !inherited => {
+        overridable[index] = false;
+        params.insert(key, param);
+      }
+      Some(_) => duplicates.push(param.name.to_string()),
+      None => {
+        overridable.push(inherited);
+        params.insert(key, param);
+      }
     }
   }
-  Some(params)
+  if duplicates.is_empty() {
+    Some(params)
+  } else {
+    ctx.error(Error::duplicate_keys(duplicates));
+    None
+  }
 }
 
 fn op_parse_request<'src>(
@@ -130,33 +271,65 @@ fn op_parse_request<'src>(
       return None;
     }
   };
-  let body = match body.as_item() {
-    Some(body) => body,
-    None => {
-      ctx.error(Error::unsupported_ref("request_body"));
-
-      return None;
-    }
-  };
-  if let Some((mime, inner)) = body.content.first() {
-    let mime = match mime.as_str().try_into() {
+  let body = resolve_component(ctx, body)?;
+  let mut content = Vec::with_capacity(body.content.len());
+  for (mime, inner) in body.content.iter() {
+    let media_type = match mime.as_str().try_into() {
       Ok(v) => v,
       Err(..) => {
-        ctx.error(Error::invalid_value("mime type", mime.to_string()));
-        return None;
+        ctx.error(Error::invalid_value("media type", mime.to_string()));
+        continue;
       }
     };
-    inner
-      .schema
-      .as_ref()
-      .and_then(|b| resolve_type(ctx, None, b))
-      .map(|ty| ast::RequestBody {
-        mime_type: mime,
-        headers: vec![],
-        ty,
-      })
-  } else {
+    let ty = match inner.schema.as_ref() {
+      Some(schema) => match resolve_type(ctx, None, schema) {
+        Some(ty) => ty,
+        None => continue,
+      },
+      None => ast::TypeRef::Type(ast::Type::Any),
+    };
+    let mut encoding = IndexMap::with_capacity(inner.encoding.len());
+    for (name, data) in inner.encoding.iter() {
+      // a comma-separated list of media types may be given, use the first one
+      let media_type = match data
+        .content_type
+        .as_deref()
+        .and_then(|v| v.split(',').next())
+        .map(|v| v.trim().try_into())
+      {
+        Some(Ok(v)) => Some(v),
+        Some(Err(..)) => {
+          ctx.error(Error::invalid_value(
+            "media type",
+            data.content_type.clone().unwrap_or_default(),
+          ));
+          continue;
+        }
+        None => None,
+      };
+      let style = data.style.as_ref().map(query_style);
+      encoding.insert(
+        name.as_str().into(),
+        ast::Encoding {
+          media_type,
+          style: style.unwrap_or(ast::ParameterStyle::Form),
+          // `explode` is deserialized with a default of `false`, so it can't be told apart
+          // from a missing value. it only defaults to `true` when the style is also missing.
+          explode: data.explode || style.is_none(),
+          allow_reserved: data.allow_reserved,
+        },
+      );
+    }
+    content.push(ast::Content {
+      media_type,
+      ty,
+      encoding,
+    });
+  }
+  if content.is_empty() {
     None
+  } else {
+    Some(ast::RequestBody { content })
   }
 }
 
@@ -165,9 +338,10 @@ fn parse_code<'src>(
   code: &'src oapi3::StatusCode,
 ) -> Option<ast::Code> {
   match code {
-    oapi3::StatusCode::Code(v) => Some((*v).into()),
+    oapi3::StatusCode::Code(v) => Some(ast::Code::Exact(*v)),
+    oapi3::StatusCode::Range(v @ 1..=5) => Some(ast::Code::Range(*v as u8)),
     oapi3::StatusCode::Range(_) => {
-      ctx.error(Error::unsupported("status code range"));
+      ctx.error(Error::invalid_value("status code range", code.to_string()));
       None
     }
   }
@@ -177,106 +351,245 @@ fn parse_response<'src>(
   ctx: &mut Context<'src>,
   res: &'src oapi3::Response,
 ) -> Option<ast::Response<'src>> {
-  let res = match res.content.get("application/json") {
-    Some(res) => res,
-    None => {
-      if !res.content.is_empty() {
-        ctx.error(Error::generic(
-          "only `application/json` mime-type is supported",
-        ));
+  let headers = parse_response_headers(ctx, res);
+  let mut content = vec![];
+  for (mime, inner) in res.content.iter() {
+    match ast::MediaType::try_from(mime.as_str()) {
+      Ok(media_type) => content.push((media_type, inner)),
+      Err(..) => {
+        ctx.error(Error::invalid_value("media type", mime.to_string()))
       }
-      return None;
     }
-  };
+  }
+  if content.is_empty() && !res.content.is_empty() {
+    return None;
+  }
+  // JSON is preferred, as it is the only one which is typed by its schema
+  content
+    .sort_by_key(|(media_type, _)| media_type.kind() != ast::MediaKind::Json);
+  let mut alternatives = Vec::with_capacity(content.len());
+  for (media_type, inner) in content {
+    let ty = match inner.schema.as_ref() {
+      Some(schema) => resolve_type(ctx, None, schema)?,
+      None => ast::TypeRef::Type(ast::Type::Any),
+    };
+    alternatives.push(ast::Content {
+      media_type,
+      ty,
+      encoding: IndexMap::new(),
+    });
+  }
   Some(ast::Response {
-    body: res
-      .schema
-      .as_ref()
-      .and_then(|schema| resolve_type(ctx, None, schema)),
+    content: alternatives,
+    headers,
   })
 }
 
+fn parse_response_headers<'src>(
+  ctx: &mut Context<'src>,
+  res: &'src oapi3::Response,
+) -> IndexMap<Cow<'src, str>, ast::TypeRef<'src>> {
+  let mut headers = IndexMap::with_capacity(res.headers.len());
+  for (name, header) in res.headers.iter() {
+    // `Content-Type` is described by the response's content instead
+    if name.eq_ignore_ascii_case("content-type") {
+      continue;
+    }
+    let _scope = ctx.scope(format!("header {name}"));
+    let header = match resolve_component(ctx, header) {
+      Some(header) => header,
+      None => continue,
+    };
+    let schema = match &header.format {
+      oapi3::ParameterSchemaOrContent::Schema(schema) => Some(schema),
+      oapi3::ParameterSchemaOrContent::Content(content) => {
+        content.first().and_then(|(_, media)| media.schema.as_ref())
+      }
+    };
+    let ty = match schema {
+      Some(schema) => match resolve_type(ctx, None, schema) {
+        Some(ty) => ty,
+        None => continue,
+      },
+      None => ast::TypeRef::Type(ast::Type::String),
+    };
+    let ty = if header.required {
+      ty
+    } else {
+      ast::TypeRef::Type(ast::Type::Optional(Box::new(ty)))
+    };
+    headers.insert(name.as_str().into(), ty);
+  }
+  headers
+}
+
 fn op_parse_responses<'src>(
   ctx: &mut Context<'src>,
   op: &'src oapi3::Operation,
 ) -> ast::Responses<'src> {
   let _scope = ctx.scope("responses");
-  let default =
-    op.responses
-      .default
-      .as_ref()
-      .and_then(|res| match res.as_item() {
-        Some(res) => parse_response(ctx, res),
-        None => {
-          ctx.error(Error::unsupported_ref("default"));
-          None
-        }
-      });
-  let mut specific = Vec::with_capacity(op.responses.responses.len());
-  for (code, res) in op.responses.responses.iter() {
-    match res.as_item() {
-      Some(res) => {
-        let code = match parse_code(ctx, code) {
-          Some(c) => c,
-          None => continue,
-        };
-        let _scope = ctx.scope(format!("{}", code));
-        let res = match parse_response(ctx, res) {
-          Some(res) => res,
-          None => continue,
-        };
-        specific.push((code, res));
-      }
-      None => {
-        ctx.error(Error::unsupported_ref("by_code"));
-      }
-    }
+  let mut responses = Vec::with_capacity(op.responses.responses.len() + 1);
+  let codes = op
+    .responses
+    .responses
+    .iter()
+    .map(|(code, res)| (parse_code(ctx, code), res))
+    .chain(
+      op.responses
+        .default
+        .iter()
+        .map(|res| (Some(ast::Code::Default), res)),
+    )
+    .collect::<Vec<_>>();
+  for (code, res) in codes {
+    let code = match code {
+      Some(c) => c,
+      None => continue,
+    };
+    let _scope = ctx.scope(format!("{}", code));
+    let res = match resolve_component(ctx, res) {
+      Some(res) => res,
+      None => continue,
+    };
+    let res = match parse_response(ctx, res) {
+      Some(res) => res,
+      None => continue,
+    };
+    responses.push((code, res));
   }
-  ast::Responses { default, specific }
+  responses.sort_by_key(|(code, _)| *code);
+  responses
 }
 
 fn parse_security<'src>(
   ctx: &mut Context<'src>,
-  security: &[IndexMap<String, Vec<String>>],
-) -> Option<ast::Security<'src>> {
-  for supported_schemes in security.iter() {
-    for (name, _) in supported_schemes.iter() {
-      if let Some(scheme) = ctx.security.get(name.as_str()) {
-        return Some(scheme.clone());
-      } else {
+  security: &'src [IndexMap<String, Vec<String>>],
+) -> ast::SecurityRequirements<'src> {
+  let _scope = ctx.scope("security");
+  let mut requirements = Vec::with_capacity(security.len());
+  // a requirement naming an unknown scheme can never be satisfied
+  'requirements: for requirement in security.iter() {
+    for name in requirement.keys() {
+      if !ctx.security.contains_key(name.as_str()) {
         ctx.error(Error::generic(format!("unknown security schema {name}")));
+        continue 'requirements;
       }
     }
+    requirements.push(
+      requirement
+        .iter()
+        .map(|(name, scopes)| {
+          let scopes = scopes.iter().map(|s| s.as_str().into()).collect();
+          (name.as_str().into(), scopes)
+        })
+        .collect(),
+    );
   }
-  None
+  requirements
 }
 
-fn op_parse_security<'src>(
+fn parse_servers<'src>(
   ctx: &mut Context<'src>,
+  servers: &'src [oapi3::Server],
+) -> ast::Servers<'src> {
+  let _scope = ctx.scope("servers");
+  let mut out = Vec::with_capacity(servers.len());
+  for server in servers {
+    let variables: IndexMap<_, _> = server
+      .variables
+      .iter()
+      .flatten()
+      .map(|(name, var)| {
+        let var = ast::ServerVariable {
+          default: var.default.as_str().into(),
+          enumeration: var
+            .enumeration
+            .iter()
+            .map(|v| v.as_str().into())
+            .collect(),
+          description: var.description.as_deref().map(Into::into),
+        };
+        (Cow::from(name.as_str()), var)
+      })
+      .collect();
+    let mut valid = true;
+    for (name, var) in variables.iter() {
+      if !var.enumeration.is_empty() && !var.enumeration.contains(&var.default)
+      {
+        ctx.error(Error::generic(format!(
+          "default `{}` of server variable `{name}` is not one of its enum values",
+          var.default
+        )));
+        valid = false;
+      }
+    }
+    let used = server
+      .url
+      .split('{')
+      .skip(1)
+      .filter_map(|s| s.split_once('}'));
+    for (name, _) in used {
+      if !variables.contains_key(name) {
+        ctx.error(Error::generic(format!(
+          "server variable `{name}` of `{}` is not defined",
+          server.url
+        )));
+        valid = false;
+      }
+    }
+    if valid {
+      out.push(ast::Server {
+        url: server.url.as_str().into(),
+        description: server.description.as_deref().map(Into::into),
+        variables,
+      });
+    }
+  }
+  out
+}
+
+/// Operation servers take precedence over the ones of their path
+fn op_parse_servers<'src>(
+  ctx: &mut Context<'src>,
+  item: &'src oapi3::PathItem,
   op: &'src oapi3::Operation,
-) -> Option<ast::Security<'src>> {
-  let _scope = ctx.scope("security");
-  if let Some(security) = &op.security {
-    parse_security(ctx, security)
+) -> ast::Servers<'src> {
+  if op.servers.is_empty() {
+    parse_servers(ctx, &item.servers)
   } else {
-    None
+    parse_servers(ctx, &op.servers)
+  }
+}
+
+/// Operations without requirements of their own inherit the global ones
+fn op_parse_security<'src>(
+  ctx: &mut Context<'src>,
+  op: &'src oapi3::Operation,
+  default: &ast::SecurityRequirements<'src>,
+) -> ast::SecurityRequirements<'src> {
+  match &op.security {
+    Some(security) => parse_security(ctx, security),
+    None => default.clone(),
   }
 }
 
 fn parse_route<'src>(
   ctx: &mut Context<'src>,
   uri: &'src str,
+  item: &'src oapi3::PathItem,
   method: ast::Method,
   op: &'src oapi3::Operation,
+  security: &ast::SecurityRequirements<'src>,
 ) -> Option<ast::Route<'src>> {
   let _scope = ctx.scope(format!("{} {}", method, uri));
   let name = op_parse_name(ctx, op);
   let endpoint = uri.into();
   let description = op_parse_desc(op);
-  let parameters = op_parse_params(ctx, op);
+  let parameters = op_parse_params(ctx, item, op);
   let request = op_parse_request(ctx, op);
   let responses = op_parse_responses(ctx, op);
-  let security = op_parse_security(ctx, op);
+  let security = op_parse_security(ctx, op, security);
+  let servers = op_parse_servers(ctx, item, op);
 
   Some(ast::Route {
     name: name?,
@@ -287,6 +600,7 @@ fn parse_route<'src>(
     request_body: request,
     responses,
     security,
+    servers,
   })
 }
 
@@ -316,7 +630,12 @@ fn string_type<'src>(
     .filter_map(|v| v.map(Cow::from))
     .collect::<Vec<_>>();
   if variants.is_empty() {
-    ast::Type::String
+    match str.format {
+      oapi3::VariantOrUnknownOrEmpty::Item(oapi3::StringFormat::Binary) => {
+        ast::Type::Binary
+      }
+      _ => ast::Type::String,
+    }
   } else {
     ast::Type::Enum(variants)
   }
@@ -435,7 +754,7 @@ fn resolve_reference<'src>(
   ctx: &mut Context<'src>,
   name: &'src str,
 ) -> Option<ast::TypeRef<'src>> {
-  let name = name.split('/').last().unwrap_or(name);
+  let name = name.split('/').next_back().unwrap_or(name);
   if !ctx.types.contains_key(name) {
     if ctx.can_insert {
       if let Some(components) = ctx.components {
@@ -471,7 +790,7 @@ fn resolve_type_boxed<'src>(
 ) -> Option<ast::TypeRef<'src>> {
   use oapi3::ReferenceOr::*;
   match schema {
-    Item(schema) => resolve_item(ctx, name, &**schema),
+    Item(schema) => resolve_item(ctx, name, schema),
     Reference { reference } => resolve_reference(ctx, reference.as_str()),
   }
 }
@@ -485,6 +804,83 @@ fn parse_types(ctx: &mut Context<'_>) {
   }
 }
 
+fn parse_oauth2_flows(flows: &oapi3::OAuth2Flows) -> Vec<ast::OAuth2Flow<'_>> {
+  // the flattened fields of `OAuth2Flows` don't necessarily hold the flow
+  // they are named after, so flows are told apart by their variant instead
+  let mut out: Vec<ast::OAuth2Flow> = vec![];
+  let all = [
+    &flows.implicit,
+    &flows.password,
+    &flows.client_credentials,
+    &flows.authorization_code,
+  ];
+  for flow in all.into_iter().flatten() {
+    let (kind, authorization_url, token_url, refresh_url, scopes) = match flow {
+      oapi3::OAuth2Flow::Implicit {
+        authorization_url,
+        refresh_url,
+        scopes,
+      } => (
+        ast::OAuth2FlowKind::Implicit,
+        Some(authorization_url),
+        None,
+        refresh_url,
+        scopes,
+      ),
+      oapi3::OAuth2Flow::Password {
+        refresh_url,
+        token_url,
+        scopes,
+      } => (
+        ast::OAuth2FlowKind::Password,
+        None,
+        Some(token_url),
+        refresh_url,
+        scopes,
+      ),
+      oapi3::OAuth2Flow::ClientCredentials {
+        refresh_url,
+        token_url,
+        scopes,
+      } => (
+        ast::OAuth2FlowKind::ClientCredentials,
+        None,
+        Some(token_url),
+        refresh_url,
+        scopes,
+      ),
+      oapi3::OAuth2Flow::AuthorizationCode {
+        authorization_url,
+        token_url,
+        refresh_url,
+        scopes,
+      } => (
+        ast::OAuth2FlowKind::AuthorizationCode,
+        Some(authorization_url),
+        Some(token_url),
+        refresh_url,
+        scopes,
+      ),
+    };
+    if out.iter().any(|f| f.kind == kind) {
+      continue;
+    }
+    out.push(ast::OAuth2Flow {
+      kind,
+      authorization_url: authorization_url.map(|v| v.as_str().into()),
+      token_url: token_url.map(|v| v.as_str().into()),
+      refresh_url: refresh_url.as_deref().map(Into::into),
+      scopes: scopes
+        .iter()
+        .map(|(k, v)| (k.as_str().into(), v.as_str().into()))
+        .collect(),
+    });
+  }
+  // in the order of the variants, whichever field held them
+  out.sort_by_key(|f| f.kind);
+  out
+}
+
 fn parse_security_schemes(ctx: &mut Context<'_>) {
   if let Some(components) = ctx.components {
     let _scope = ctx.scope("components");
@@ -498,28 +894,59 @@ fn parse_security_schemes(ctx: &mut Context<'_>) {
             location,
             name: key,
             ..
-          } => match location {
-            oapi3::APIKeyLocation::Header => {
-              ctx.security.insert(
-                name.clone().into(),
-                ast::Security {
-                  name: name.clone().into(),
-                  key: key.clone().into(),
-                },
-              );
-            }
-            oapi3::APIKeyLocation::Query => {
-              ctx.error(Error::unsupported("Query API key authentication"));
-            }
-            oapi3::APIKeyLocation::Cookie => {
-              ctx.error(Error::unsupported("Cookie API key authentication"));
-            }
-          },
-          oapi3::SecurityScheme::HTTP { .. } => {
-            ctx.error(Error::unsupported("HTTP authentication"));
+          } => {
+            let location = match location {
+              oapi3::APIKeyLocation::Header => ast::ApiKeyLocation::Header,
+              oapi3::APIKeyLocation::Query => ast::ApiKeyLocation::Query,
+              oapi3::APIKeyLocation::Cookie => ast::ApiKeyLocation::Cookie,
+            };
+            ctx.security.insert(
+              name.clone().into(),
+              ast::Security::ApiKey {
+                name: name.clone().into(),
+                key: key.clone().into(),
+                location,
+              },
+            );
+          }
+          oapi3::SecurityScheme::HTTP {
+            scheme,
+            bearer_format,
+            ..
+          } => {
+            // scheme names are case-insensitive
+            let security = match scheme.to_ascii_lowercase().as_str() {
+              "basic" => ast::Security::Basic {
+                name: name.clone().into(),
+              },
+              "bearer" => ast::Security::Bearer {
+                name: name.clone().into(),
+                format: bearer_format.clone().map(Into::into),
+              },
+              _ => {
+                ctx.error(Error::unsupported(format!(
+                  "HTTP {scheme} authentication"
+                )));
+                continue;
+              }
+            };
+            ctx.security.insert(name.clone().into(), security);
           }
-          oapi3::SecurityScheme::OAuth2 { .. } => {
-            ctx.error(Error::unsupported("OAuth2 authentication"));
+          oapi3::SecurityScheme::OAuth2 { flows, .. } => {
+            let flows = parse_oauth2_flows(flows);
+            if flows.is_empty() {
+              ctx.error(Error::generic(format!(
+                "OAuth2 security scheme `{name}` declares no flows"
+              )));
+              continue;
+            }
+            ctx.security.insert(
+              name.clone().into(),
+              ast::Security::OAuth2 {
+                name: name.clone().into(),
+                flows,
+              },
+            );
           }
           oapi3::SecurityScheme::OpenIDConnect { .. } => {
             ctx.error(Error::unsupported("OpenID authentication"));
@@ -530,6 +957,34 @@ fn parse_security_schemes(ctx: &mut Context<'_>) {
   }
 }
 
+/// Reports names which the generated code would declare more than once, such
+/// as a schema named like a helper of the runtime, or an operation named like
+/// the `Raw` variant of another one
+fn check_names(ctx: &mut Context<'_>, ast: &ast::Ast<'_>) {
+  use crate::emit::{declared_names, Declarer};
+  let mut declared: IndexMap<Cow<str>, Declarer> = IndexMap::new();
+  for (name, by) in declared_names(ast) {
+    let first = match declared.get(&name) {
+      Some(first) => first,
+      None => {
+        declared.insert(name, by);
+        continue;
+      }
+    };
+    // the runtime and the client come first, so they never clash late
+    let _scope = ctx.scope_opt(match by {
+      Declarer::Route(route) => {
+        Some(format!("{} {}", route.method, route.endpoint))
+      }
+      Declarer::Scheme(_) | Declarer::Schema => Some("components".into()),
+      Declarer::Server => Some("servers".into()),
+      Declarer::Runtime | Declarer::Client => None,
+    });
+    let message = format!("`{name}` is declared by both {first} and {by}");
+    ctx.error(Error::generic(message));
+  }
+}
+
 impl ast::AsAst for oapi3::OpenAPI {
   type Error = Error;
   fn as_ast(&self) -> Result<ast::Ast<'_>, (ast::Ast<'_>, Vec<self::Error>)> {
@@ -539,29 +994,34 @@ impl ast::AsAst for oapi3::OpenAPI {
     let security = self
       .security
       .as_ref()
-      .and_then(|v| parse_security(&mut ctx, v));
+      .map(|v| parse_security(&mut ctx, v))
+      .unwrap_or_default();
+    let servers = parse_servers(&mut ctx, &self.servers);
 
     ctx.can_insert = true;
     parse_types(&mut ctx);
     ctx.can_insert = false;
 
     let mut routes = vec![];
-    for (uri, info) in self
+    for (uri, item) in self
       .paths
       .iter()
       .filter_map(|(uri, item)| Some((uri, item.as_item()?)))
     {
-      routes.extend(info.iter().filter_map(|(m, op)| {
-        parse_route(&mut ctx, uri, m.try_into().expect("Invalid method"), op)
+      routes.extend(item.iter().filter_map(|(m, op)| {
+        let method = m.try_into().expect("Invalid method");
+        parse_route(&mut ctx, uri, item, method, op, &security)
       }));
     }
 
     let ast = ast::Ast {
       routes,
-      types: ctx.types,
-      schemes: ctx.security,
+      types: std::mem::take(&mut ctx.types),
+      schemes: std::mem::take(&mut ctx.security),
       security,
+      servers,
     };
+    check_names(&mut ctx, &ast);
     if ctx.errors.is_empty() {
       Ok(ast)
     } else {
@@ -569,3 +1029,874 @@ impl ast::AsAst for oapi3::OpenAPI {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast::AsAst;
+  use pretty_assertions::assert_eq;
+  use serde_json::json;
+
+  fn spec(
+    paths: serde_json::Value,
+    components: serde_json::Value,
+  ) -> oapi3::OpenAPI {
+    serde_json::from_value(json!({
+      "openapi": "3.0.0",
+      "info": { "title": "test", "version": "1.0.0" },
+      "paths": paths,
+      "components": components,
+    }))
+    .unwrap()
+  }
+
+  #[test]
+  fn referenced_components() {
+    let spec = spec(
+      json!({
+        "/jobs": {
+          "post": {
+            "operationId": "createJob",
+            "parameters": [{ "$ref": "#/components/parameters/Limit" }],
+            "requestBody": { "$ref": "#/components/requestBodies/Job" },
+            "responses": {
+              "200": { "$ref": "#/components/responses/Ok" },
+              "default": { "$ref": "#/components/responses/Error" }
+            }
+          }
+        }
+      }),
+      json!({
+        "parameters": {
+          "Limit": {
+            "in": "query",
+            "name": "limit",
+            "required": true,
+            "schema": { "type": "integer" }
+          }
+        },
+        "requestBodies": {
+          "Job": {
+            "content": { "application/json": { "schema": { "type": "string" } } }
+          }
+        },
+        "responses": {
+          "Ok": { "$ref": "#/components/responses/Error" },
+          "Error": {
+            "description": "",
+            "content": { "application/json": { "schema": { "type": "boolean" } } }
+          }
+        }
+      }),
+    );
+    let ast = spec.as_ast().unwrap();
+    let route = &ast.routes[0];
+    let parameters: ast::Parameters = map! {
+        ("limit".into(), ast::ParameterKind::Query) => ast::Parameter {
+          name: "limit".into(),
+          description: None,
+          kind: ast::ParameterKind::Query,
+          ty: ast::TypeRef::Type(ast::Type::Number),
+          style: ast::ParameterStyle::Form,
+          explode: true,
+          allow_reserved: false,
+          media_type: None,
+        }
+    };
+    assert_eq!(route.parameters, parameters);
+    assert_eq!(
+      route.request_body.as_ref().map(|b| &b.content[..]),
+      Some(
+        &[ast::Content {
+          media_type: "application/json".try_into().unwrap(),
+          ty: ast::TypeRef::Type(ast::Type::String),
+          encoding: IndexMap::new(),
+        }][..]
+      )
+    );
+    let error = ast::Response {
+      content: vec![ast::Content {
+        media_type: "application/json".try_into().unwrap(),
+        ty: ast::TypeRef::Type(ast::Type::Boolean),
+        encoding: IndexMap::new(),
+      }],
+      headers: IndexMap::new(),
+    };
+    assert_eq!(
+      route.responses,
+      vec![(200u16.into(), error.clone()), (ast::Code::Default, error)]
+    );
+  }
+
+  #[test]
+  fn path_item_parameters() {
+    let spec = spec(
+      json!({
+        "/jobs/{jobId}": {
+          "parameters": [
+            { "in": "path", "name": "jobId", "required": true, "schema": { "type": "string" } },
+            { "in": "query", "name": "verbose", "schema": { "type": "boolean" } }
+          ],
+          "get": {
+            "operationId": "getJob",
+            "parameters": [
+              { "in": "query", "name": "verbose", "required": true, "schema": { "type": "boolean" } }
+            ],
+            "responses": {}
+          },
+          "delete": {
+            "operationId": "deleteJob",
+            "responses": {}
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = spec.as_ast().unwrap();
+    let param = |name: &'static str, kind, ty| ast::Parameter {
+      name: name.into(),
+      description: None,
+      kind,
+      ty,
+      style: match kind {
+        ast::ParameterKind::Path => ast::ParameterStyle::Simple,
+        _ => ast::ParameterStyle::Form,
+      },
+      explode: kind != ast::ParameterKind::Path,
+      allow_reserved: false,
+      media_type: None,
+    };
+    let job_id = param(
+      "jobId",
+      ast::ParameterKind::Path,
+      ast::TypeRef::Type(ast::Type::String),
+    );
+    let get: ast::Parameters = map! {
+      ("jobId".into(), ast::ParameterKind::Path) => job_id.clone(),
+      ("verbose".into(), ast::ParameterKind::Query) => param(
+        "verbose",
+        ast::ParameterKind::Query,
+        ast::TypeRef::Type(ast::Type::Boolean),
+      )
+    };
+    let delete: ast::Parameters = map! {
+      ("jobId".into(), ast::ParameterKind::Path) => job_id,
+      ("verbose".into(), ast::ParameterKind::Query) => param(
+        "verbose",
+        ast::ParameterKind::Query,
+        ast::TypeRef::Type(ast::Type::Optional(Box::new(
+          ast::TypeRef::Type(ast::Type::Boolean),
+        ))),
+      )
+    };
+    assert_eq!(ast.routes[0].parameters, get);
+    assert_eq!(ast.routes[1].parameters, delete);
+  }
+
+  #[test]
+  fn parameter_name_conflict() {
+    let conflict = spec(
+      json!({
+        "/jobs/{id}": {
+          "parameters": [
+            { "in": "path", "name": "id", "required": true, "schema": { "type": "string" } }
+          ],
+          "get": {
+            "operationId": "getJob",
+            "parameters": [
+              { "in": "query", "name": "id", "schema": { "type": "string" } }
+            ],
+            "responses": {}
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = conflict.as_ast().unwrap();
+    // the same name may be used in different locations
+    assert_eq!(
+      ast.routes[0]
+        .parameters
+        .keys()
+        .map(|(name, kind)| (&name[..], *kind))
+        .collect::<Vec<_>>(),
+      vec![
+        ("id", ast::ParameterKind::Path),
+        ("id", ast::ParameterKind::Query)
+      ]
+    );
+
+    let duplicates = spec(
+      json!({
+        "/jobs": {
+          "get": {
+            "operationId": "listJobs",
+            "parameters": [
+              { "in": "query", "name": "id", "schema": { "type": "string" } },
+              { "in": "query", "name": "id", "schema": { "type": "number" } }
+            ],
+            "responses": {}
+          }
+        }
+      }),
+      json!({}),
+    );
+    let (_, errors) = duplicates.as_ast().unwrap_err();
+    assert_eq!(
+      errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+      vec!["Error in get /jobs.parameters: found duplicate keys: `[\"id\"]`"]
+    );
+  }
+
+  #[test]
+  fn parameter_styles() {
+    let spec = spec(
+      json!({
+        "/jobs/{ids}": {
+          "get": {
+            "operationId": "listJobs",
+            "parameters": [
+              { "in": "path", "name": "ids", "required": true, "style": "label", "explode": true, "schema": { "type": "array", "items": { "type": "string" } } },
+              { "in": "query", "name": "filter", "style": "deepObject", "schema": { "type": "object" } },
+              { "in": "query", "name": "tags", "explode": false, "allowReserved": true, "schema": { "type": "array", "items": { "type": "string" } } },
+              { "in": "header", "name": "X-Ids", "schema": { "type": "array", "items": { "type": "string" } } }
+            ],
+            "responses": {}
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = spec.as_ast().unwrap();
+    let styles = ast.routes[0]
+      .parameters
+      .values()
+      .map(|p| (p.name.as_ref(), p.style, p.explode, p.allow_reserved))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      styles,
+      vec![
+        ("ids", ast::ParameterStyle::Label, true, false),
+        ("filter", ast::ParameterStyle::DeepObject, false, false),
+        ("tags", ast::ParameterStyle::Form, false, true),
+        ("X-Ids", ast::ParameterStyle::Simple, false, false),
+      ]
+    );
+  }
+
+  #[test]
+  fn content_parameter() {
+    let spec = spec(
+      json!({
+        "/jobs": {
+          "get": {
+            "operationId": "listJobs",
+            "parameters": [{
+              "in": "query",
+              "name": "filter",
+              "required": true,
+              "content": {
+                "application/json": {
+                  "schema": { "type": "object", "properties": { "status": { "type": "string" } } }
+                }
+              }
+            }],
+            "responses": {}
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = spec.as_ast().unwrap();
+    let param = &ast.routes[0].parameters[0];
+    assert_eq!(param.media_type, "application/json".try_into().ok());
+    assert_eq!(
+      param.ty,
+      ast::TypeRef::Type(ast::Type::Object(map! {
+        "status" => ast::TypeRef::Type(ast::Type::Optional(Box::new(
+          ast::TypeRef::Type(ast::Type::String)
+        )))
+      }))
+    );
+  }
+
+  #[test]
+  fn media_types() {
+    let spec = spec(
+      json!({
+        "/jobs/{id}": {
+          "patch": {
+            "operationId": "updateJob",
+            "requestBody": {
+              "content": {
+                "application/merge-patch+json; charset=utf-8": { "schema": { "type": "string" } },
+                "application/octet-stream": {},
+                "application/xml": {}
+              }
+            },
+            "responses": {}
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = spec.as_ast().unwrap();
+    let body = ast.routes[0].request_body.as_ref().unwrap();
+    let media_types = body
+      .content
+      .iter()
+      .map(|c| (c.media_type.to_string(), c.media_type.kind()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      media_types,
+      vec![
+        (
+          "application/merge-patch+json; charset=utf-8".to_string(),
+          ast::MediaKind::Json
+        ),
+        (
+          "application/octet-stream".to_string(),
+          ast::MediaKind::Binary
+        ),
+        ("application/xml".to_string(), ast::MediaKind::Text),
+      ]
+    );
+    let kind = |v: &'static str| ast::MediaType::try_from(v).unwrap().kind();
+    assert_eq!(kind("text/event-stream"), ast::MediaKind::EventStream);
+    assert_eq!(kind("application/x-ndjson"), ast::MediaKind::JsonLines);
+    assert_eq!(body.content[0].media_type.suffix, Some("json"));
+    assert_eq!(
+      body.content[0].media_type.params,
+      vec![("charset", "utf-8")]
+    );
+
+    let invalid: Result<ast::MediaType, _> = "application".try_into();
+    assert!(invalid.is_err());
+  }
+
+  #[test]
+  fn form_encoding() {
+    let spec = spec(
+      json!({
+        "/jobs": {
+          "post": {
+            "operationId": "createJob",
+            "requestBody": {
+              "content": {
+                "application/x-www-form-urlencoded": {
+                  "schema": {
+                    "type": "object",
+                    "properties": {
+                      "tags": { "type": "array", "items": { "type": "string" } },
+                      "meta": { "type": "object" }
+                    }
+                  },
+                  "encoding": {
+                    "tags": { "style": "pipeDelimited" },
+                    "meta": { "contentType": "application/json" }
+                  }
+                }
+              }
+            },
+            "responses": {}
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = spec.as_ast().unwrap();
+    let content = &ast.routes[0].request_body.as_ref().unwrap().content[0];
+    assert_eq!(content.media_type.kind(), ast::MediaKind::FormUrlEncoded);
+    assert_eq!(
+      content.encoding["tags"],
+      ast::Encoding {
+        media_type: None,
+        style: ast::ParameterStyle::PipeDelimited,
+        explode: false,
+        allow_reserved: false,
+      }
+    );
+    assert_eq!(
+      content.encoding["meta"],
+      ast::Encoding {
+        media_type: "application/json".try_into().ok(),
+        style: ast::ParameterStyle::Form,
+        explode: true,
+        allow_reserved: false,
+      }
+    );
+  }
+
+  #[test]
+  fn multipart_body() {
+    let spec = spec(
+      json!({
+        "/avatars": {
+          "put": {
+            "operationId": "uploadAvatar",
+            "requestBody": {
+              "content": {
+                "multipart/form-data": {
+                  "schema": {
+                    "type": "object",
+                    "required": ["file"],
+                    "properties": {
+                      "file": { "type": "string", "format": "binary" }
+                    }
+                  },
+                  "encoding": {
+                    "file": { "contentType": "image/png, image/jpeg" }
+                  }
+                }
+              }
+            },
+            "responses": {}
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = spec.as_ast().unwrap();
+    let content = &ast.routes[0].request_body.as_ref().unwrap().content[0];
+    assert_eq!(
+      content.ty,
+      ast::TypeRef::Type(ast::Type::Object(map! {
+        "file" => ast::TypeRef::Type(ast::Type::Binary)
+      }))
+    );
+    assert_eq!(
+      content.encoding["file"].media_type,
+      "image/png".try_into().ok()
+    );
+  }
+
+  #[test]
+  fn status_code_ranges() {
+    let spec = spec(
+      json!({
+        "/jobs": {
+          "get": {
+            "operationId": "listJobs",
+            "responses": {
+              "default": { "description": "" },
+              "4XX": { "description": "" },
+              "404": { "description": "" },
+              "2XX": { "description": "" },
+              "200": { "description": "" }
+            }
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = spec.as_ast().unwrap();
+    let codes = ast.routes[0]
+      .responses
+      .iter()
+      .map(|(code, _)| *code)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      codes,
+      vec![
+        ast::Code::Exact(200),
+        ast::Code::Exact(404),
+        ast::Code::Range(2),
+        ast::Code::Range(4),
+        ast::Code::Default,
+      ]
+    );
+    assert!(ast::Code::Range(2).is_ok());
+    assert!(ast::Code::Exact(299).is_ok());
+    assert!(!ast::Code::Default.is_ok());
+    assert_eq!(ast::Code::try_from(404u32), Ok(ast::Code::Exact(404)));
+    assert!(ast::Code::try_from(65_736u64).is_err());
+  }
+
+  #[test]
+  fn response_media_types() {
+    let spec = spec(
+      json!({
+        "/exports/{id}": {
+          "get": {
+            "operationId": "getExport",
+            "responses": {
+              "200": {
+                "description": "",
+                "content": {
+                  "text/csv": {},
+                  "application/json": { "schema": { "type": "string" } }
+                }
+              },
+              "204": { "description": "" },
+              "404": { "description": "", "content": { "text/plain": {} } }
+            }
+          }
+        }
+      }),
+      json!({}),
+    );
+    let ast = spec.as_ast().unwrap();
+    let bodies = ast.routes[0]
+      .responses
+      .iter()
+      .map(|(_, res)| {
+        res
+          .content
+          .iter()
+          .map(|c| c.media_type.to_string())
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      bodies,
+      vec![
+        vec!["application/json", "text/csv"],
+        vec![],
+        vec!["text/plain"],
+      ]
+    );
+  }
+
+  #[test]
+  fn response_headers() {
+    let spec = spec(
+      json!({
+        "/jobs": {
+          "post": {
+            "operationId": "createJob",
+            "responses": {
+              "201": {
+                "description": "",
+                "headers": {
+                  "Location": { "$ref": "#/components/headers/Location" },
+                  "X-Total-Count": { "schema": { "type": "integer" } },
+                  "Content-Type": { "schema": { "type": "string" } }
+                }
+              }
+            }
+          }
+        }
+      }),
+      json!({
+        "headers": {
+          "Location": { "required": true, "schema": { "type": "string" } }
+        }
+      }),
+    );
+    let ast = spec.as_ast().unwrap();
+    let headers: IndexMap<Cow<str>, ast::TypeRef> = map! {
+      "Location" => ast::TypeRef::Type(ast::Type::String),
+      "X-Total-Count" => ast::TypeRef::Type(ast::Type::Optional(Box::new(
+        ast::TypeRef::Type(ast::Type::Number)
+      )))
+    };
+    assert_eq!(ast.routes[0].responses[0].1.headers, headers);
+  }
+
+  #[test]
+  fn servers() {
+    let mut spec = spec(
+      json!({
+        "/jobs": {
+          "servers": [{ "url": "https://jobs.example.com" }],
+          "get": { "operationId": "listJobs", "responses": {} },
+          "post": {
+            "operationId": "createJob",
+            "servers": [{ "url": "https://write.example.com" }],
+            "responses": {}
+          }
+        },
+        "/users": {
+          "get": { "operationId": "listUsers", "responses": {} }
+        }
+      }),
+      json!({}),
+    );
+    spec.servers = serde_json::from_value(json!([{
+      "url": "https://{region}.example.com:{port}/v1",
+      "description": "Production",
+      "variables": {
+        "region": { "default": "eu", "enum": ["eu", "us"] },
+        "port": { "default": "443" }
+      }
+    }]))
+    .unwrap();
+    let ast = spec.as_ast().unwrap();
+    assert_eq!(
+      ast.servers,
+      vec![ast::Server {
+        url: "https://{region}.example.com:{port}/v1".into(),
+        description: Some("Production".into()),
+        variables: map! {
+          "region" => ast::ServerVariable {
+            default: "eu".into(),
+            enumeration: vec!["eu".into(), "us".into()],
+            description: None,
+          },
+          "port" => ast::ServerVariable {
+            default: "443".into(),
+            enumeration: vec![],
+            description: None,
+          }
+        },
+      }]
+    );
+    let urls = ast
+      .routes
+      .iter()
+      .map(|r| (&*r.name, r.servers.iter().map(|s| &*s.url).collect()))
+      .collect::<Vec<(_, Vec<_>)>>();
+    assert_eq!(
+      urls,
+      vec![
+        ("listJobs", vec!["https://jobs.example.com"]),
+        ("createJob", vec!["https://write.example.com"]),
+        ("listUsers", vec![]),
+      ]
+    );
+  }
+
+  #[test]
+  fn invalid_server_variables() {
+    let mut spec = spec(json!({}), json!({}));
+    spec.servers = serde_json::from_value(json!([
+      { "url": "https://{region}.example.com" },
+      {
+        "url": "https://{env}.example.com",
+        "variables": { "env": { "default": "dev", "enum": ["prod"] } }
+      }
+    ]))
+    .unwrap();
+    let (ast, errors) = spec.as_ast().unwrap_err();
+    assert!(ast.servers.is_empty());
+    assert_eq!(
+      errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+      vec![
+        "Error in servers: server variable `region` of `https://{region}.example.com` is not defined",
+        "Error in servers: default `dev` of server variable `env` is not one of its enum values",
+      ]
+    );
+  }
+
+  #[test]
+  fn security_schemes() {
+    let spec = spec(
+      json!({}),
+      json!({
+        "securitySchemes": {
+          "token": { "type": "http", "scheme": "Bearer", "bearerFormat": "JWT" },
+          "login": { "type": "http", "scheme": "basic" },
+          "key": { "type": "apiKey", "in": "query", "name": "api_key" },
+          "digest": { "type": "http", "scheme": "digest" }
+        }
+      }),
+    );
+    let (ast, errors) = spec.as_ast().unwrap_err();
+    let schemes: ast::SecuritySchemes = map! {
+      "token" => ast::Security::Bearer {
+        name: "token".into(),
+        format: Some("JWT".into()),
+      },
+      "login" => ast::Security::Basic { name: "login".into() },
+      "key" => ast::Security::ApiKey {
+        name: "key".into(),
+        key: "api_key".into(),
+        location: ast::ApiKeyLocation::Query,
+      }
+    };
+    assert_eq!(ast.schemes, schemes);
+    assert_eq!(
+      errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+      vec!["Error in components: HTTP digest authentication is unsupported"]
+    );
+  }
+
+  #[test]
+  fn oauth2_flows() {
+    let spec = spec(
+      json!({}),
+      json!({
+        "securitySchemes": {
+          "oauth": {
+            "type": "oauth2",
+            "flows": {
+              "clientCredentials": {
+                "tokenUrl": "https://auth.example.com/token",
+                "scopes": { "jobs:read": "Read jobs" }
+              },
+              "authorizationCode": {
+                "authorizationUrl": "https://auth.example.com/authorize",
+                "tokenUrl": "https://auth.example.com/token",
+                "refreshUrl": "https://auth.example.com/refresh",
+                "scopes": { "jobs:write": "Write jobs" }
+              }
+            }
+          }
+        }
+      }),
+    );
+    let ast = spec.as_ast().unwrap();
+    let flows = match &ast.schemes["oauth"] {
+      ast::Security::OAuth2 { flows, .. } => flows,
+      other => panic!("unexpected scheme {other:?}"),
+    };
+    let kinds = flows.iter().map(|f| f.kind).collect::<Vec<_>>();
+    assert_eq!(
+      kinds,
+      vec![
+        ast::OAuth2FlowKind::ClientCredentials,
+        ast::OAuth2FlowKind::AuthorizationCode
+      ]
+    );
+    let code = flows
+      .iter()
+      .find(|f| f.kind == ast::OAuth2FlowKind::AuthorizationCode)
+      .unwrap();
+    assert_eq!(
+      code,
+      &ast::OAuth2Flow {
+        kind: ast::OAuth2FlowKind::AuthorizationCode,
+        authorization_url: Some("https://auth.example.com/authorize".into()),
+        token_url: Some("https://auth.example.com/token".into()),
+        refresh_url: Some("https://auth.example.com/refresh".into()),
+        scopes: map! { "jobs:write" => "Write jobs".into() },
+      }
+    );
+  }
+
+  #[test]
+  fn security_requirements() {
+    let mut spec = spec(
+      json!({
+        "/jobs": {
+          "get": { "operationId": "listJobs", "responses": {} },
+          "post": {
+            "operationId": "createJob",
+            "security": [{ "key": [], "oauth": ["jobs:write"] }, {}],
+            "responses": {}
+          }
+        },
+        "/health": {
+          "get": { "operationId": "health", "security": [], "responses": {} }
+        },
+        "/users": {
+          "get": {
+            "operationId": "listUsers",
+            "security": [{ "unknown": [] }, { "oauth": [] }],
+            "responses": {}
+          }
+        }
+      }),
+      json!({
+        "securitySchemes": {
+          "key": { "type": "apiKey", "in": "header", "name": "X-Key" },
+          "oauth": {
+            "type": "oauth2",
+            "flows": {
+              "clientCredentials": { "tokenUrl": "/token", "scopes": {} }
+            }
+          }
+        }
+      }),
+    );
+    spec.security = Some(vec![map! { "key" => vec![] }]);
+    let (ast, errors) = spec.as_ast().unwrap_err();
+    let security = ast
+      .routes
+      .iter()
+      .map(|r| (&*r.name, r.security.clone()))
+      .collect::<Vec<_>>();
+    let expected: Vec<(&str, ast::SecurityRequirements)> = vec![
+      ("health", vec![]),
+      ("listJobs", vec![map! { "key" => vec![] }]),
+      (
+        "createJob",
+        vec![
+          map! {
+            "key" => vec![],
+            "oauth" => vec!["jobs:write".into()]
+          },
+          IndexMap::new(),
+        ],
+      ),
+      ("listUsers", vec![map! { "oauth" => vec![] }]),
+    ];
+    assert_eq!(security, expected);
+    assert_eq!(
+      errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+      vec!["Error in get /users.security: unknown security schema unknown"]
+    );
+  }
+
+  #[test]
+  fn cyclic_reference() {
+    let spec = spec(
+      json!({
+        "/jobs": {
+          "get": {
+            "operationId": "listJobs",
+            "parameters": [{ "$ref": "#/components/parameters/A" }],
+            "responses": {}
+          }
+        }
+      }),
+      json!({
+        "parameters": {
+          "A": { "$ref": "#/components/parameters/B" },
+          "B": { "$ref": "#/components/parameters/A" }
+        }
+      }),
+    );
+    let (ast, errors) = spec.as_ast().unwrap_err();
+    assert!(ast.routes.is_empty());
+    assert_eq!(
+      errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+      vec![
+        "Error in get /jobs.parameters: reference to `#/components/parameters/A` is cyclic"
+      ]
+    );
+  }
+
+  #[test]
+  fn name_clashes() {
+    let spec = spec(
+      json!({
+        "/jobs": {
+          "get": {
+            "operationId": "getJob",
+            "responses": {}
+          },
+          "post": {
+            "operationId": "getJobRaw",
+            "responses": {}
+          }
+        },
+        "/init": {
+          "post": {
+            "operationId": "init",
+            "responses": {}
+          }
+        }
+      }),
+      json!({
+        "schemas": {
+          "Result": { "type": "string" },
+          "ApiError": { "type": "string" },
+          "getJobError": { "type": "string" },
+          "Job": { "type": "string" }
+        }
+      }),
+    );
+    let (_, errors) = spec.as_ast().unwrap_err();
+    assert_eq!(
+      errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+      vec![
+        "Error in components: `ApiError` is declared by both the runtime and a schema",
+        "Error in components: `Result` is declared by both the runtime and a schema",
+        "Error in post /init: `init` is declared by both the client and the operation `init`",
+        "Error in get /jobs: `getJobError` is declared by both a schema and the operation `getJob`",
+        "Error in post /jobs: `getJobRaw` is declared by both the operation `getJob` and the operation `getJobRaw`",
+      ]
+    );
+  }
+}
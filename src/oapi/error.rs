@@ -7,6 +7,13 @@ pub struct Scope {
 }
 
 impl Scope {
+  /// Copies the current scope, so that it is not affected by guards dropped later.
+  pub fn snapshot(&self) -> Scope {
+    Scope {
+      inner: Rc::new(RefCell::new(self.inner.borrow().clone())),
+    }
+  }
+
   pub fn named<S>(&self, name: S) -> ScopeGuard
   where
     S: Into<String>,
@@ -70,6 +77,10 @@ impl Error {
     ErrorKind::UnresolvedReference(name.into())
   }
 
+  pub fn cyclic_ref<A: Into<Cow<'static, str>>>(name: A) -> ErrorKind {
+    ErrorKind::CyclicReference(name.into())
+  }
+
   pub fn required_field<A: Into<Cow<'static, str>>>(field: A) -> ErrorKind {
     ErrorKind::RequiredField(field.into())
   }
@@ -113,6 +124,8 @@ pub enum ErrorKind {
   UnsupportedReference(Cow<'static, str>),
   #[error("could not resolve reference to `{0}`")]
   UnresolvedReference(Cow<'static, str>),
+  #[error("reference to `{0}` is cyclic")]
+  CyclicReference(Cow<'static, str>),
   #[error("field `{0}` is required")]
   RequiredField(Cow<'static, str>),
   #[error("field `{0}` is required, but may be substituted with `{1}`")]
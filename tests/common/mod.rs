@@ -0,0 +1,34 @@
+//! Helpers shared by the integration tests
+
+use std::{fs, path::PathBuf, process::Command};
+
+/// Writes `source` to `name` in a scratch directory and returns its path
+pub fn write(name: &str, source: &str) -> PathBuf {
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("generated");
+  fs::create_dir_all(&dir).unwrap();
+  let path = dir.join(name);
+  fs::write(&path, source).unwrap();
+  path
+}
+
+/// Type checks a generated client with `tsc`, the check is skipped when
+/// `tsc` is not installed
+pub fn type_check(name: &str, client: &str) {
+  if Command::new("tsc").arg("--version").output().is_err() {
+    eprintln!("skipping the type check of {name}, `tsc` is not installed");
+    return;
+  }
+  let path = write(&format!("{name}.ts"), client);
+  let output = Command::new("tsc")
+    .args(["--noEmit", "--strict", "--target", "es2022"])
+    .args(["--lib", "es2022,dom,dom.iterable"])
+    .arg(&path)
+    .output()
+    .unwrap();
+  assert!(
+    output.status.success(),
+    "{} does not type check:\n{}",
+    path.display(),
+    String::from_utf8_lossy(&output.stdout)
+  );
+}
@@ -1,3 +1,5 @@
+mod common;
+
 macro_rules! try_parse_and_emit {
   ($test_name:ident, $name:literal) => {
     #[test]
@@ -8,7 +10,9 @@ macro_rules! try_parse_and_emit {
           .unwrap();
       match oapi.as_ast() {
         Ok(v) => {
-          println!("{}", oagen::emit::emit(v));
+          let client = oagen::emit::emit(v);
+          println!("{client}");
+          common::type_check(stringify!($test_name), &client);
         }
         Err((v, e)) => {
           println!("{v:#?}");
@@ -21,7 +25,7 @@ macro_rules! try_parse_and_emit {
 }
 
 try_parse_and_emit!(job_queue, "./data/job-queue.json");
-//try_parse_and_emit!(notifications, "./data/notifications.json");
+try_parse_and_emit!(notifications, "./data/notifications.json");
 try_parse_and_emit!(sandboxes, "./data/sandboxes.json");
 try_parse_and_emit!(sapi_importer, "./data/sapi-importer.json");
 try_parse_and_emit!(scheduler, "./data/scheduler.json");
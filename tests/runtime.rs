@@ -0,0 +1,816 @@
+//! Runs generated clients against a stubbed `fetch`, using the type stripping
+//! of Node.js, so the tests are skipped where it is not available
+
+mod common;
+
+use oagen::{
+  ast::AsAst,
+  emit::{emit_with, Options},
+};
+use serde_json::{json, Value};
+use std::process::{Command, Output};
+
+fn spec(paths: Value, components: Value) -> openapiv3::OpenAPI {
+  serde_json::from_value(json!({
+    "openapi": "3.0.0",
+    "info": { "title": "test", "version": "1.0.0" },
+    "paths": paths,
+    "components": components,
+  }))
+  .unwrap()
+}
+
+/// Helpers available to every script:
+/// - `stub(...responses)` returns a `fetch` which records its calls in
+///   `requests` and resolves to the given responses in order, repeating the
+///   last one
+/// - `json(body, init)` creates a JSON `Response`
+/// - `rejects(promise)` resolves to the error the promise was rejected with
+const PRELUDE: &str = r#"
+import assert from "node:assert/strict";
+const requests: { url: string; init: any }[] = [];
+function stub(...responses: any[]): any {
+  return async (url: string, init: any) => {
+    requests.push({ url, init });
+    const next = responses.length > 1 ? responses.shift() : responses[0];
+    return typeof next === "function" ? next(url, init) : next.clone();
+  };
+}
+function json(body: unknown, init: any = {}) {
+  const headers = { "Content-Type": "application/json", ...init.headers };
+  return new Response(JSON.stringify(body), { status: 200, ...init, headers });
+}
+async function rejects(promise: Promise<unknown>): Promise<any> {
+  try {
+    await promise;
+  } catch (e) {
+    return e;
+  }
+  assert.fail("expected a rejection");
+}
+"#;
+
+fn node(args: &[&str]) -> std::io::Result<Output> {
+  Command::new("node")
+    .args(["--experimental-strip-types", "--no-warnings"])
+    .args(args)
+    .output()
+}
+
+/// Generates the client of `spec`, type checks it and runs it together with
+/// `script` as an ES module, `script` is wrapped in a block so that it may
+/// shadow exports
+fn run(name: &str, spec: openapiv3::OpenAPI, options: Options, script: &str) {
+  let ast = match spec.as_ast() {
+    Ok(ast) => ast,
+    Err((_, errors)) => panic!("{errors:#?}"),
+  };
+  let client = emit_with(ast, options);
+  common::type_check(name, &client);
+
+  if !matches!(node(&["--eval", ""]), Ok(output) if output.status.success()) {
+    eprintln!("skipping {name}, `node` does not support type stripping");
+    return;
+  }
+  let path = common::write(
+    &format!("{name}.mts"),
+    &format!("{client}\n{PRELUDE}\n{{{script}}}"),
+  );
+  let output = node(&[path.to_str().unwrap()]).unwrap();
+  assert!(
+    output.status.success(),
+    "{} failed:\n{}",
+    path.display(),
+    String::from_utf8_lossy(&output.stderr)
+  );
+}
+
+#[test]
+fn json_response() {
+  let spec = spec(
+    json!({
+      "/jobs/{id}": {
+        "get": {
+          "operationId": "getJob",
+          "parameters": [
+            { "in": "path", "name": "id", "required": true, "schema": { "type": "string" } }
+          ],
+          "responses": {
+            "200": {
+              "description": "",
+              "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Job" } } }
+            }
+          }
+        }
+      }
+    }),
+    json!({
+      "schemas": {
+        "Job": {
+          "type": "object",
+          "required": ["id"],
+          "properties": { "id": { "type": "integer" } }
+        }
+      }
+    }),
+  );
+  run(
+    "json_response",
+    spec,
+    Options::default(),
+    r#"
+    const client = new ApiClient("http://api.test", stub(json({ id: 1 })));
+    const job: Job = await client.getJob({ id: "7" });
+    assert.deepEqual(job, { id: 1 });
+    assert.equal(requests[0].url, "http://api.test/jobs/7");
+    assert.equal(requests[0].init.method, "get");
+    "#,
+  );
+}
+
+#[test]
+fn path_parameters() {
+  let spec = spec(
+    json!({
+      "/files/{id}/meta": {
+        "get": {
+          "operationId": "getMeta",
+          "parameters": [
+            { "in": "path", "name": "id", "required": true, "schema": { "type": "string" } }
+          ],
+          "responses": {
+            "200": {
+              "description": "",
+              "content": { "application/json": { "schema": { "type": "object" } } }
+            }
+          }
+        }
+      }
+    }),
+    json!({}),
+  );
+  run(
+    "path_parameters",
+    spec,
+    Options::default(),
+    r#"
+    const client = new ApiClient("http://api.test/v1", stub(json({})));
+    await client.getMeta({ id: "a/b c?d#e" });
+    assert.equal(requests[0].url, "http://api.test/v1/files/a%2Fb%20c%3Fd%23e/meta");
+    await client.getMeta({ id: "..." });
+    assert.equal(requests[1].url, "http://api.test/v1/files/.../meta");
+    // dot segments would resolve to another path, such as `/v1/meta`
+    for (const id of [".", ".."]) {
+      const error = await rejects(client.getMeta({ id }));
+      assert.match(error.message, /invalid value of the path parameter id/);
+    }
+    assert.equal(requests.length, 2);
+    "#,
+  );
+}
+
+#[test]
+fn form_body() {
+  let spec = spec(
+    json!({
+      "/search": {
+        "post": {
+          "operationId": "search",
+          "requestBody": {
+            "content": {
+              "application/x-www-form-urlencoded": {
+                "schema": {
+                  "type": "object",
+                  "properties": {
+                    "q": { "type": "string" },
+                    "path": { "type": "string" },
+                    "tags": { "type": "array", "items": { "type": "string" } }
+                  }
+                },
+                "encoding": {
+                  "path": { "allowReserved": true },
+                  "tags": { "style": "pipeDelimited", "explode": false }
+                }
+              }
+            }
+          },
+          "responses": { "204": { "description": "" } }
+        }
+      }
+    }),
+    json!({}),
+  );
+  run(
+    "form_body",
+    spec,
+    Options::default(),
+    r#"
+    const client = new ApiClient("http://api.test", stub(new Response(null, { status: 204 })));
+    await client.search({ q: "a+b c&d", path: "/a/b?c", tags: ["x", "y"] });
+    const { init } = requests[0];
+    assert.equal(init.headers["Content-Type"], "application/x-www-form-urlencoded");
+    assert.equal(init.body, "q=a%2Bb%20c%26d&path=/a/b?c&tags=x|y");
+    "#,
+  );
+}
+
+#[test]
+fn multipart_body() {
+  let spec = spec(
+    json!({
+      "/avatars": {
+        "put": {
+          "operationId": "uploadAvatar",
+          "requestBody": {
+            "content": {
+              "multipart/form-data": {
+                "schema": {
+                  "type": "object",
+                  "properties": {
+                    "file": { "type": "string", "format": "binary" },
+                    "meta": { "type": "object" }
+                  }
+                },
+                "encoding": {
+                  "file": { "contentType": "image/png" }
+                }
+              }
+            }
+          },
+          "responses": { "204": { "description": "" } }
+        }
+      }
+    }),
+    json!({}),
+  );
+  run(
+    "multipart_body",
+    spec,
+    Options::default(),
+    r#"
+    const client = new ApiClient("http://api.test", stub(new Response(null, { status: 204 })));
+    await client.uploadAvatar({ file: new File(["png"], "a.png"), meta: { size: 3 } });
+    let form = requests[0].init.body;
+    assert.equal(form.get("file").type, "image/png");
+    assert.equal(form.get("file").name, "a.png");
+    assert.equal(form.get("meta").type, "application/json");
+    assert.equal(await form.get("meta").text(), '{"size":3}');
+
+    // files keep their own type
+    await client.uploadAvatar({ file: new Blob(["gif"], { type: "image/gif" }) });
+    assert.equal(requests[1].init.body.get("file").type, "image/gif");
+
+    // prepared forms are sent as-is
+    form = new FormData();
+    form.append("file", "raw");
+    await client.uploadAvatar(form);
+    assert.equal(requests[2].init.body, form);
+    assert.equal(requests[2].init.headers["Content-Type"], undefined);
+    "#,
+  );
+}
+
+#[test]
+fn response_media_types() {
+  let spec = spec(
+    json!({
+      "/exports/{id}": {
+        "get": {
+          "operationId": "getExport",
+          "parameters": [
+            { "in": "path", "name": "id", "required": true, "schema": { "type": "string" } }
+          ],
+          "responses": {
+            "200": {
+              "description": "",
+              "content": {
+                "text/csv": {},
+                "application/json": { "schema": { "type": "object" } },
+                "image/*": {}
+              }
+            },
+            "404": { "description": "", "content": { "text/plain": {} } }
+          }
+        }
+      }
+    }),
+    json!({}),
+  );
+  run(
+    "response_media_types",
+    spec,
+    Options::default(),
+    r#"
+    const client = new ApiClient("http://api.test", stub(
+      json({ a: 1 }, { headers: { "Content-Type": "application/json; charset=utf-8" } }),
+      new Response("a,b\n1,2", { headers: { "Content-Type": "text/csv" } }),
+      new Response("{", { headers: { "Content-Type": "image/png" } }),
+      new Response("missing", { status: 404, headers: { "Content-Type": "text/plain" } }),
+    ));
+    assert.deepEqual(await client.getExport({ id: "1" }), { a: 1 });
+    assert.equal(requests[0].init.headers.Accept, "application/json, image/*, text/csv, text/plain");
+    assert.equal(await client.getExport({ id: "1" }), "a,b\n1,2");
+    assert.ok((await client.getExport({ id: "1" })) instanceof Blob);
+    const error = await rejects(client.getExport({ id: "1" }));
+    assert.equal(error.body, "missing");
+    "#,
+  );
+}
+
+#[test]
+fn response_headers() {
+  let spec = spec(
+    json!({
+      "/jobs": {
+        "post": {
+          "operationId": "createJob",
+          "responses": {
+            "201": {
+              "description": "",
+              "headers": {
+                "Location": { "schema": { "type": "string" } },
+                "X-Retries": { "schema": { "type": "integer" } }
+              },
+              "content": { "application/json": { "schema": { "type": "object" } } }
+            }
+          }
+        }
+      }
+    }),
+    json!({}),
+  );
+  run(
+    "response_headers",
+    spec,
+    Options::default(),
+    r#"
+    const client = new ApiClient("http://api.test", stub(
+      json({ id: 1 }, { status: 201, headers: { Location: "/jobs/1", "X-Retries": "2" } }),
+      json({ queued: true }, { status: 202 }),
+    ));
+    assert.deepEqual(await client.createJob(), {
+      data: { id: 1 },
+      headers: { Location: "/jobs/1", "X-Retries": 2 },
+    });
+    assert.deepEqual(await client.createJob(), { data: { queued: true }, headers: {} });
+    "#,
+  );
+}
+
+#[test]
+fn operation_servers() {
+  let spec = spec(
+    json!({
+      "/jobs": {
+        "get": {
+          "operationId": "listJobs",
+          "responses": { "204": { "description": "" } }
+        },
+        "post": {
+          "operationId": "createJob",
+          "servers": [
+            { "url": "https://write.example.com" },
+            {
+              "url": "https://{region}.example.com/{version}",
+              "variables": {
+                "region": { "default": "eu", "enum": ["eu", "us"] },
+                "version": { "default": "v1" }
+              }
+            }
+          ],
+          "responses": { "204": { "description": "" } }
+        }
+      }
+    }),
+    json!({}),
+  );
+  run(
+    "operation_servers",
+    spec,
+    Options::default(),
+    r#"
+    let client = new ApiClient("http://api.test", stub(new Response(null, { status: 204 })));
+    await client.listJobs();
+    await client.createJob();
+    await client.createJob({ baseUrl: createJobServer1({ region: "us" }) });
+    await client.listJobs({ baseUrl: "http://other.test" });
+    assert.deepEqual(requests.map((r) => r.url), [
+      "http://api.test/jobs",
+      "https://write.example.com/jobs",
+      "https://us.example.com/v1/jobs",
+      "http://other.test/jobs",
+    ]);
+
+    // defaults replace the servers of every operation
+    requests.length = 0;
+    client = new ApiClient("http://api.test", stub(new Response(null, { status: 204 })), {
+      baseUrl: "http://proxy.test",
+    });
+    await client.createJob();
+    assert.equal(requests[0].url, "http://proxy.test/jobs");
+    "#,
+  );
+}
+
+#[test]
+fn oauth2_tokens() {
+  let spec = spec(
+    json!({
+      "/jobs": {
+        "get": {
+          "operationId": "listJobs",
+          "security": [{ "oauth": ["jobs:read"] }],
+          "responses": { "204": { "description": "" } }
+        }
+      }
+    }),
+    json!({
+      "securitySchemes": {
+        "oauth": {
+          "type": "oauth2",
+          "flows": {
+            "clientCredentials": {
+              "tokenUrl": "http://auth.test/token",
+              "refreshUrl": "http://auth.test/refresh",
+              "scopes": { "jobs:read": "" }
+            }
+          }
+        }
+      }
+    }),
+  );
+  run(
+    "oauth2_tokens",
+    spec,
+    Options::default(),
+    r#"
+    // the token endpoint issues `t1`, `t2`, ... and omits the refresh token once refreshed
+    const tokens = [];
+    const stored = [];
+    const oauth = oauthClient({
+      clientId: "id",
+      clientSecret: "secret",
+      scopes: ["jobs:read"],
+      onToken: (token) => stored.push(token),
+      fetch: async (url, init) => {
+        tokens.push({ url, params: Object.fromEntries(init.body) });
+        const refreshed = init.body.get("grant_type") === "refresh_token";
+        return json({
+          access_token: `t${tokens.length}`,
+          expires_in: 3600,
+          ...(refreshed ? {} : { refresh_token: "r1" }),
+        });
+      },
+    });
+    const client = new ApiClient("http://api.test", oauth, stub(
+      new Response(null, { status: 204 }),
+      new Response(null, { status: 204 }),
+      new Response(null, { status: 204 }),
+      new Response(null, { status: 401 }),
+      new Response(null, { status: 204 }),
+    ));
+    const authorization = () => requests.map((r) => r.init.headers.Authorization);
+
+    // concurrent requests share the first token, later ones reuse it
+    await Promise.all([client.listJobs(), client.listJobs()]);
+    await client.listJobs();
+    assert.deepEqual(tokens, [{
+      url: "http://auth.test/token",
+      params: { grant_type: "client_credentials", scope: "jobs:read", client_id: "id", client_secret: "secret" },
+    }]);
+    assert.deepEqual(authorization(), ["Bearer t1", "Bearer t1", "Bearer t1"]);
+    assert.equal(stored.length, 1);
+    assert.ok(stored[0].expiresAt > Date.now() + 3500 * 1000);
+
+    // rejected tokens are refreshed, keeping the refresh token
+    await client.listJobs();
+    assert.deepEqual(tokens[1], {
+      url: "http://auth.test/refresh",
+      params: { grant_type: "refresh_token", refresh_token: "r1", client_id: "id", client_secret: "secret" },
+    });
+    assert.deepEqual(authorization().slice(3), ["Bearer t1", "Bearer t2"]);
+    assert.equal(oauth.token.refreshToken, "r1");
+
+    // tokens about to expire are refreshed before they are sent
+    oauth.token.expiresAt = Date.now() + 1000;
+    await client.listJobs();
+    assert.equal(tokens.length, 3);
+    assert.equal(requests.at(-1).init.headers.Authorization, "Bearer t3");
+    "#,
+  );
+}
+
+#[test]
+fn credential_providers() {
+  let spec = spec(
+    json!({
+      "/jobs": {
+        "get": {
+          "operationId": "listJobs",
+          "security": [{ "token": [] }, { "login": [] }],
+          "responses": { "204": { "description": "" } }
+        }
+      }
+    }),
+    json!({
+      "securitySchemes": {
+        "token": { "type": "http", "scheme": "bearer" },
+        "login": { "type": "http", "scheme": "basic" }
+      }
+    }),
+  );
+  run(
+    "credential_providers",
+    spec,
+    Options::default(),
+    r#"
+    const authorization = () => requests.map((r) => r.init.headers.Authorization);
+
+    // rotating credentials are provided anew for every request
+    let calls = 0;
+    let client = new ApiClient("http://api.test", undefined, () => `t${++calls}`, stub(
+      new Response(null, { status: 204 }),
+    ));
+    await client.listJobs();
+    await client.listJobs();
+    assert.deepEqual(authorization(), ["Bearer t1", "Bearer t2"]);
+
+    // alternatives without a provided value are skipped
+    requests.length = 0;
+    let login = undefined;
+    client = new ApiClient("http://api.test", () => login, () => undefined, stub(
+      new Response(null, { status: 204 }),
+    ));
+    const error = await rejects(client.listJobs());
+    assert.equal(error.message, "missing credentials, the operation requires token or login");
+    login = { username: "a", password: "b" };
+    await client.listJobs();
+    assert.deepEqual(authorization(), [`Basic ${btoa("a:b")}`]);
+
+    // `onUnauthorized` retries once with the credentials provided then
+    requests.length = 0;
+    calls = 0;
+    const hooks = [];
+    client = new ApiClient(
+      "http://api.test",
+      undefined,
+      () => `t${++calls}`,
+      stub(new Response(null, { status: 401 })),
+      (schemes) => {
+        hooks.push(schemes);
+        return true;
+      },
+    );
+    await rejects(client.listJobs());
+    assert.deepEqual(authorization(), ["Bearer t1", "Bearer t2"]);
+    assert.deepEqual(hooks, [["token"]]);
+    "#,
+  );
+}
+
+#[test]
+fn optional_auth() {
+  let spec = spec(
+    json!({
+      "/jobs": {
+        "get": {
+          "operationId": "listJobs",
+          "security": [{}, { "token": [] }],
+          "responses": { "204": { "description": "" } }
+        }
+      }
+    }),
+    json!({
+      "securitySchemes": { "token": { "type": "http", "scheme": "bearer" } }
+    }),
+  );
+  run(
+    "optional_auth",
+    spec,
+    Options::default(),
+    r#"
+    const fetch = stub(new Response(null, { status: 204 }));
+    // configured credentials are sent even though the empty alternative comes first
+    await new ApiClient("http://api.test", "t", fetch).listJobs();
+    await new ApiClient("http://api.test", () => undefined, fetch).listJobs();
+    await new ApiClient("http://api.test", undefined, fetch).listJobs();
+    const authorization = requests.map((r) => r.init.headers.Authorization);
+    assert.deepEqual(authorization, ["Bearer t", undefined, undefined]);
+    "#,
+  );
+}
+
+#[test]
+fn default_client() {
+  let spec = spec(
+    json!({
+      "/jobs": {
+        "get": {
+          "operationId": "listJobs",
+          "responses": { "200": { "description": "", "content": { "application/json": {} } } }
+        }
+      }
+    }),
+    json!({}),
+  );
+  run(
+    "default_client",
+    spec,
+    Options::default(),
+    r#"
+    // free functions call the instance configured by `init`
+    globalThis.fetch = stub(json([1]));
+    init("http://api.test");
+    assert.deepEqual(await listJobs(), [1]);
+    assert.equal(requests[0].url, "http://api.test/jobs");
+    "#,
+  );
+}
+
+#[test]
+fn timeouts() {
+  let spec = spec(
+    json!({
+      "/jobs": {
+        "get": {
+          "operationId": "listJobs",
+          "security": [{ "token": [] }],
+          "responses": { "200": { "description": "", "content": { "application/json": {} } } }
+        },
+        "post": {
+          "operationId": "createJob",
+          "responses": { "202": { "description": "" } }
+        }
+      },
+      "/events": {
+        "get": {
+          "operationId": "listEvents",
+          "responses": {
+            "200": { "description": "", "content": { "application/x-ndjson": {} } }
+          }
+        }
+      }
+    }),
+    json!({
+      "securitySchemes": { "token": { "type": "http", "scheme": "bearer" } }
+    }),
+  );
+  run(
+    "timeouts",
+    spec,
+    Options::default(),
+    r#"
+    const { getEventListeners } = await import("node:events");
+    const listeners = async (signal) => {
+      // the signal is released once the body settled, which takes a few more ticks
+      await new Promise((resolve) => setTimeout(resolve));
+      return getEventListeners(signal, "abort").length;
+    };
+    const never = new Promise(() => {});
+    // headers arrive at once, the body never ends
+    const stalled = () => new Response(new ReadableStream({ start: (c) => c.enqueue(new TextEncoder().encode("[")) }));
+
+    // the timeout covers providing credentials
+    let token = () => never;
+    // cancelling a clone waits for the original as well, so responses are created anew
+    const client = new ApiClient("http://api.test", () => token(), stub(stalled, () => json([1])));
+    let error = await rejects(client.listJobs({ timeoutMs: 20 }));
+    assert.ok(error instanceof TimeoutError);
+    assert.equal(requests.length, 0);
+
+    // aborted requests don't provide credentials
+    let provided = 0;
+    token = () => `t${++provided}`;
+    error = await rejects(client.listJobs({ signal: AbortSignal.abort(new Error("cancelled")) }));
+    assert.equal(error.message, "cancelled");
+    assert.equal(provided, 0);
+
+    // and it covers reading the body, unless the raw response is returned
+    error = await rejects(client.listJobs({ timeoutMs: 20 }));
+    assert.ok(error instanceof TimeoutError);
+    const controller = new AbortController();
+    const response = await client.listJobsRaw({ signal: controller.signal, timeoutMs: 20 });
+    assert.equal(response.status, 200);
+    assert.equal(await listeners(controller.signal), 1);
+    // the caller's signal is released once the body is cancelled
+    await response.body.cancel();
+    assert.equal(await listeners(controller.signal), 0);
+
+    // or once it was read
+    const signal = new AbortController().signal;
+    for (let i = 0; i < 3; i++) assert.deepEqual(await client.listJobs({ signal, timeoutMs: 1000 }), [1]);
+    assert.equal(await listeners(signal), 0);
+
+    // bodies of responses which declare none are discarded
+    const lines = '{"n":1}\n{"n":2}\n';
+    const other = new ApiClient("http://api.test", undefined, stub(
+      () => new Response("queued", { status: 202 }),
+      () => new Response(lines, { headers: { "Content-Type": "application/x-ndjson" } }),
+    ));
+    assert.equal(await other.createJob({ signal }), undefined);
+    assert.equal(await listeners(signal), 0);
+
+    // streams hold on to the caller's signal until they end or are cancelled
+    let events = await other.listEvents({ signal });
+    assert.equal(await listeners(signal), 1);
+    const items = [];
+    for await (const event of events) items.push(event);
+    assert.deepEqual(items, [{ n: 1 }, { n: 2 }]);
+    assert.equal(await listeners(signal), 0);
+    events = await other.listEvents({ signal });
+    for await (const event of events) break;
+    assert.equal(await listeners(signal), 0);
+    "#,
+  );
+}
+
+#[test]
+fn retries() {
+  let spec = spec(
+    json!({
+      "/jobs": {
+        "get": {
+          "operationId": "listJobs",
+          "security": [{ "token": [] }],
+          "responses": { "204": { "description": "" } }
+        },
+        "post": {
+          "operationId": "createJob",
+          "security": [{ "token": [] }],
+          "responses": { "204": { "description": "" } }
+        }
+      }
+    }),
+    json!({
+      "securitySchemes": { "token": { "type": "http", "scheme": "bearer" } }
+    }),
+  );
+  run(
+    "retries",
+    spec,
+    Options::default(),
+    r#"
+    // backoff waits the longest possible delay
+    Math.random = () => 1;
+    let prepared = 0;
+    const client = (baseUrl, ...responses) =>
+      new ApiClient(baseUrl, () => `t${++prepared}`, stub(...responses), undefined, {
+        retry: { maxAttempts: 3, baseDelayMs: 1 },
+      });
+    const status = (status, headers = {}) => new Response(null, { status, headers });
+    const attempts = async (send) => {
+      requests.length = 0;
+      const start = Date.now();
+      const response = await send;
+      return { status: response.status, attempts: requests.length, ms: Date.now() - start };
+    };
+    const api = (...responses) => client("http://api.test", ...responses);
+
+    // only idempotent operations are retried by default
+    let result = await attempts(api(status(503), status(204)).listJobsRaw());
+    assert.deepEqual([result.status, result.attempts], [204, 2]);
+    result = await attempts(api(status(503), status(204)).createJobRaw());
+    assert.deepEqual([result.status, result.attempts], [503, 1]);
+    result = await attempts(
+      api(status(503), status(204)).createJobRaw({ retry: { maxAttempts: 3, baseDelayMs: 1, nonIdempotent: true } }),
+    );
+    assert.deepEqual([result.status, result.attempts], [204, 2]);
+
+    // up to `maxAttempts`, for the given `statuses` only
+    result = await attempts(api(status(429)).listJobsRaw());
+    assert.deepEqual([result.status, result.attempts], [429, 3]);
+    const statuses = { retry: { maxAttempts: 3, baseDelayMs: 1, statuses: [500] } };
+    result = await attempts(api(status(503), status(204)).listJobsRaw(statuses));
+    assert.deepEqual([result.status, result.attempts], [503, 1]);
+    result = await attempts(api(status(500), status(204)).listJobsRaw(statuses));
+    assert.deepEqual([result.status, result.attempts], [204, 2]);
+
+    // `Retry-After` replaces the backoff, unless it asks to wait longer than `maxDelayMs`
+    const slow = { retry: { maxAttempts: 2, baseDelayMs: 60, maxDelayMs: 1000 } };
+    result = await attempts(api(status(503, { "Retry-After": "0" }), status(204)).listJobsRaw(slow));
+    assert.deepEqual([result.status, result.attempts], [204, 2]);
+    assert.ok(result.ms < 50);
+    result = await attempts(api(status(503, { "Retry-After": "2" }), status(204)).listJobsRaw(slow));
+    assert.deepEqual([result.status, result.attempts], [503, 1]);
+    const past = new Date(Date.now() - 60000).toUTCString();
+    result = await attempts(api(status(503, { "Retry-After": past }), status(204)).listJobsRaw(slow));
+    assert.deepEqual([result.status, result.attempts], [204, 2]);
+    assert.ok(result.ms < 50);
+    const future = new Date(Date.now() + 60000).toUTCString();
+    result = await attempts(api(status(503, { "Retry-After": future }), status(204)).listJobsRaw(slow));
+    assert.deepEqual([result.status, result.attempts], [503, 1]);
+    // anything else is ignored
+    result = await attempts(api(status(503, { "Retry-After": "1.5" }), status(204)).listJobsRaw(slow));
+    assert.deepEqual([result.status, result.attempts], [204, 2]);
+    assert.ok(result.ms >= 50);
+
+    // network errors are retried, errors while preparing the request are not
+    result = await attempts(api(() => Promise.reject(new TypeError("fetch failed")), status(204)).listJobsRaw());
+    assert.deepEqual([result.status, result.attempts], [204, 2]);
+    prepared = 0;
+    const error = await rejects(client("http://[", status(204)).listJobsRaw());
+    assert.ok(error instanceof TypeError);
+    assert.equal(prepared, 1);
+    "#,
+  );
+}